@@ -0,0 +1,122 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_pixel_widgets::prelude::*;
+use bevy_pixel_widgets::{widget, UpdateModel};
+
+struct Quote {
+    pub text: String,
+    pub loading: bool,
+    pub state: ManagedState<String>,
+}
+
+#[derive(Clone)]
+enum Message {
+    LoadPressed,
+    Loaded(String),
+}
+
+impl Model for Quote {
+    type Message = Message;
+
+    fn view(&mut self) -> widget::Node<Message> {
+        let mut state = self.state.tracker();
+        let text = if self.loading { "Loading..." } else { self.text.as_str() };
+        widget::Column::new()
+            .push(widget::Button::new(state.get("load"), widget::Text::new("Load")).on_clicked(Message::LoadPressed))
+            .push(widget::Text::new(text))
+            .into_node()
+    }
+}
+
+impl<'a> UpdateModel<'a> for Quote {
+    type State = ();
+
+    fn update(&mut self, message: Self::Message, _: &mut Self::State) -> Vec<Command<Message>> {
+        match message {
+            Message::LoadPressed if !self.loading => {
+                self.loading = true;
+                // the future stands in for real async work, like an http request.
+                // its output is sent back to the ui as a message once it completes.
+                vec![Command::Await(Box::pin(async {
+                    Delay::new(Duration::from_secs(1)).await;
+                    Message::Loaded("The future is now.".to_string())
+                }))]
+            }
+            Message::LoadPressed => Vec::new(),
+            Message::Loaded(text) => {
+                self.loading = false;
+                self.text = text;
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// A future that completes after a delay, without depending on an async runtime.
+struct Delay {
+    // whether the delay is over, and the waker of the task that's waiting for it
+    state: Arc<Mutex<(bool, Option<Waker>)>>,
+}
+
+impl Delay {
+    fn new(duration: Duration) -> Self {
+        let state = Arc::new(Mutex::new((false, None::<Waker>)));
+        let thread_state = state.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(duration);
+            let mut state = thread_state.lock().unwrap();
+            state.0 = true;
+            if let Some(waker) = state.1.take() {
+                waker.wake();
+            }
+        });
+        Self { state }
+    }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.0 {
+            Poll::Ready(())
+        } else {
+            state.1 = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn update_quote(params: UpdateUiSystemParams<Quote>, state: ()) {
+    params.update(state);
+}
+
+pub fn main() {
+    pretty_env_logger::init();
+
+    App::build()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(UiPlugin::default())
+        .add_system(update_quote.system())
+        .add_startup_system(startup.system())
+        .run();
+}
+
+fn startup(mut commands: Commands, assets: Res<AssetServer>) {
+    commands.spawn_bundle(UiBundle {
+        ui: Ui::new(Quote {
+            text: "Press load to fetch a quote".to_string(),
+            loading: false,
+            state: Default::default(),
+        }),
+        draw: Default::default(),
+        stylesheet: assets.load("style.pwss"),
+        window: Default::default(),
+    });
+}
@@ -61,7 +61,7 @@ pub fn main() {
 
     App::build()
         .add_plugins(DefaultPlugins)
-        .add_plugin(UiPlugin)
+        .add_plugin(UiPlugin::default())
         .add_system(update_counter.system())
         .add_startup_system(startup.system())
         .run();
@@ -75,5 +75,6 @@ fn startup(mut commands: Commands, assets: Res<AssetServer>) {
         }),
         draw: Default::default(),
         stylesheet: assets.load("style.pwss"),
+        window: Default::default(),
     });
 }
@@ -0,0 +1,23 @@
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics};
+use bevy::prelude::*;
+
+/// Ids of the UI draw statistics that `UiPlugin` registers with bevy's `Diagnostics`.
+/// They cover the uis that are drawn into the window, not those in texture targets.
+pub struct UiDiagnostics;
+
+impl UiDiagnostics {
+    pub const DRAW_CALLS: DiagnosticId = DiagnosticId::from_u128(14515050024290291906560219807272745337);
+    pub const VERTICES: DiagnosticId = DiagnosticId::from_u128(211768092648586692346467156681723535960);
+    pub const TEXTURE_UPLOADS: DiagnosticId = DiagnosticId::from_u128(212732851899107553802329184192268035476);
+    pub const REDRAWS: DiagnosticId = DiagnosticId::from_u128(194092797734071672330443397731516335192);
+
+    pub(crate) fn setup_system(diagnostics: Option<ResMut<Diagnostics>>) {
+        // apps without the DiagnosticsPlugin simply don't get ui diagnostics
+        if let Some(mut diagnostics) = diagnostics {
+            diagnostics.add(Diagnostic::new(Self::DRAW_CALLS, "ui_draw_calls", 20));
+            diagnostics.add(Diagnostic::new(Self::VERTICES, "ui_vertices", 20));
+            diagnostics.add(Diagnostic::new(Self::TEXTURE_UPLOADS, "ui_texture_uploads", 20));
+            diagnostics.add(Diagnostic::new(Self::REDRAWS, "ui_redraws", 20));
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use bevy::math::Mat4;
+
+/// A color matrix that is applied to the final color of every UI fragment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UiColorFilter(pub Mat4);
+
+impl UiColorFilter {
+    pub const IDENTITY: Self = Self(Mat4::IDENTITY);
+
+    pub fn grayscale() -> Self {
+        Self::from_rows([
+            [0.2126, 0.7152, 0.0722, 0.0],
+            [0.2126, 0.7152, 0.0722, 0.0],
+            [0.2126, 0.7152, 0.0722, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn sepia() -> Self {
+        Self::from_rows([
+            [0.393, 0.769, 0.189, 0.0],
+            [0.349, 0.686, 0.168, 0.0],
+            [0.272, 0.534, 0.131, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Simulates red-blind color vision.
+    pub fn protanopia() -> Self {
+        Self::from_rows([
+            [0.567, 0.433, 0.0, 0.0],
+            [0.558, 0.442, 0.0, 0.0],
+            [0.0, 0.242, 0.758, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Simulates green-blind color vision.
+    pub fn deuteranopia() -> Self {
+        Self::from_rows([
+            [0.625, 0.375, 0.0, 0.0],
+            [0.7, 0.3, 0.0, 0.0],
+            [0.0, 0.3, 0.7, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Simulates blue-blind color vision.
+    pub fn tritanopia() -> Self {
+        Self::from_rows([
+            [0.95, 0.05, 0.0, 0.0],
+            [0.0, 0.433, 0.567, 0.0],
+            [0.0, 0.475, 0.525, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    fn from_rows(rows: [[f32; 4]; 4]) -> Self {
+        Self(Mat4::from_cols_array_2d(&rows).transpose())
+    }
+}
+
+impl Default for UiColorFilter {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Gamma correction that is applied to the UI after the color filter.
+/// The rgb channels of every fragment are raised to the power `1.0 / gamma`, so values above 1.0 brighten the UI.
+///
+/// The gamma should be positive. Values of zero or below are clamped to `f32::EPSILON` when rendering.
+/// Negative color channels, which a `UiColorFilter` with negative coefficients can produce, are clamped to zero
+/// before the gamma is applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UiGamma(pub f32);
+
+impl Default for UiGamma {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
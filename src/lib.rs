@@ -1,8 +1,9 @@
 use std::future::Future;
 use std::ops::{Deref, DerefMut};
 use std::pin::Pin;
-use std::sync::mpsc::{Receiver, SyncSender};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
 
 use bevy::asset::{AssetIoError, Handle};
 use bevy::ecs::bundle::Bundle;
@@ -14,10 +15,20 @@ use pixel_widgets::loader::Loader;
 pub use pixel_widgets::*;
 use pixel_widgets::{Command, EventLoop, Model};
 
+mod diagnostics;
+mod filter;
+mod modal;
 mod pipeline;
 mod pixel_widgets_node;
 mod plugin;
+mod rect;
+mod shortcut;
 mod style;
+mod target;
+#[cfg(test)]
+mod test_util;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 mod update;
 
 pub mod prelude {
@@ -25,39 +36,79 @@ pub mod prelude {
         layout::Rectangle, stylesheet::Style, tracker::ManagedState, widget::IntoNode, Command, Model, UpdateModel,
     };
 
-    pub use crate::plugin::UiPlugin;
-    pub use crate::update::UpdateUiSystemParams;
+    pub use crate::diagnostics::UiDiagnostics;
+    pub use crate::filter::{UiColorFilter, UiGamma};
+    pub use crate::modal::ModalStack;
+    pub use crate::pipeline::{
+        specialized_ui_pipeline, ui_pipeline_specialization, UiBlend, UiCustomPipeline, UiWireframe,
+        UI_FRAGMENT_SHADER_HANDLE, UI_PIPELINE_HANDLE, UI_TARGET_PIPELINE_HANDLE, UI_VERTEX_SHADER_HANDLE,
+        UI_WIREFRAME_PIPELINE_HANDLE,
+    };
+    pub use crate::pixel_widgets_node::UiRenderSettings;
+    pub use crate::plugin::{add_ui_window_pass, UiDepth, UiPlugin, UiPluginBuilder, UiWindowNodes};
+    pub use crate::rect::{rectangle_from_window_rect, window_rect_from_rectangle};
+    pub use crate::shortcut::{Shortcut, ShortcutMap};
+    pub use crate::target::{UiRenderTarget, UiTextureTarget, UiWindow};
+    pub use crate::update::{CoordinateOrigin, PointerGesture, UiInputEvents, UiInputSettings, UpdateUiSystemParams};
 
-    pub use super::style::Stylesheet;
-    pub use super::{Ui, UiBundle, UiDraw};
+    pub use super::style::{StyleOverride, StyleSource, Stylesheet};
+    pub use super::{Ui, UiBundle, UiDraw, UiLayer};
 }
 
+/// A pixel_widgets ui for the model `M`.
+///
+/// pixel_widgets expects a single coordinate space for layout and input. This crate uses logical pixels for both:
+/// the ui is resized to the logical size of the window and cursor positions are sent in logical pixels.
+/// Only the scissor rects of clipped widgets are converted to physical pixels when rendering, using the
+/// window's scale factor or the one set with `set_scale_factor`.
 pub struct Ui<M: Model + Send + Sync> {
     ui: pixel_widgets::Ui<M, EventSender<M>, DisabledLoader>,
+    sender: EventSender<M>,
     receiver: Mutex<Receiver<Command<<M as Model>::Message>>>,
     window: Option<(f32, f32)>,
+    // the size the ui is laid out at, which is all there is to go by without a window
+    size: (f32, f32),
+    input: update::InputTracker,
+    style_loaded: bool,
+    style: Option<Arc<stylesheet::Style>>,
+    style_source: style::StyleSource,
+    scale_factor: Option<f32>,
 }
 
-#[derive(Default)]
 pub struct UiDraw {
     vertices: Option<BufferId>,
     updates: Vec<pixel_widgets::draw::Update>,
     commands: Vec<pixel_widgets::draw::Command>,
+    redrawn: bool,
+    scale_factor: f32,
+    // where the vertex buffer goes when this component is dropped
+    released_buffers: Option<pixel_widgets_node::ReleasedBuffers>,
 }
 
+/// Draw order of a ui entity, higher layers are drawn on top of lower layers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct UiLayer(pub i32);
+
 #[derive(Bundle)]
 pub struct UiBundle<M: Model + Send + Sync> {
     pub ui: Ui<M>,
     pub draw: UiDraw,
     pub stylesheet: Handle<style::Stylesheet>,
+    /// The primary window by default.
+    pub window: target::UiWindow,
 }
 
 pub struct EventSender<M: Model + Send + Sync> {
     sender: SyncSender<Command<M::Message>>,
+    // cleared when the `Ui` is dropped
+    alive: Arc<AtomicBool>,
 }
 
 pub struct DisabledLoader;
 
+// number of commands that can be pending for a ui, before queueing more fails
+const COMMAND_QUEUE_CAPACITY: usize = 100;
+
 impl<M: Model + Send + Sync> EventLoop<Command<M::Message>> for EventSender<M> {
     type Error = std::sync::mpsc::SendError<Command<M::Message>>;
 
@@ -66,30 +117,150 @@ impl<M: Model + Send + Sync> EventLoop<Command<M::Message>> for EventSender<M> {
     }
 }
 
+impl<M: Model + Send + Sync> EventSender<M> {
+    /// Whether the `Ui` this sender belongs to still exists.
+    /// Producers of async work can check this before sending, instead of handling a `SendError`.
+    pub fn is_connected(&self) -> bool {
+        self.alive.load(Ordering::Acquire)
+    }
+}
+
 impl<M: Model + Send + Sync> Clone for EventSender<M> {
     fn clone(&self) -> Self {
         Self {
             sender: self.sender.clone(),
+            alive: self.alive.clone(),
         }
     }
 }
 
 impl<M: Model + Send + Sync> Ui<M> {
     pub fn new(model: M) -> Self {
-        let (sender, receiver) = std::sync::mpsc::sync_channel(100);
+        Self::with_size(model, 1280.0, 720.0)
+    }
+
+    /// Creates a ui that is laid out for a window of `width` by `height` logical pixels from the start.
+    /// It's still resized to the actual window during its first update, but this avoids a frame at the wrong size
+    /// when the window size is already known. Without its window, the ui keeps this size.
+    pub fn with_size(model: M, width: f32, height: f32) -> Self {
+        let (sender, receiver) = std::sync::mpsc::sync_channel(COMMAND_QUEUE_CAPACITY);
+        let sender = EventSender {
+            sender,
+            alive: Arc::new(AtomicBool::new(true)),
+        };
         Ui {
-            ui: pixel_widgets::Ui::new(
-                model,
-                EventSender { sender },
-                DisabledLoader,
-                Rectangle::from_wh(1280.0, 720.0),
-            ),
+            ui: pixel_widgets::Ui::new(model, sender.clone(), DisabledLoader, Rectangle::from_wh(width, height)),
+            sender,
             receiver: Mutex::new(receiver),
             window: None,
+            size: (width, height),
+            input: Default::default(),
+            style_loaded: false,
+            style: None,
+            style_source: style::StyleSource::Default,
+            scale_factor: None,
+        }
+    }
+
+    /// Sends a message to the model from outside of the ui, for example in reaction to a game event.
+    /// The message is processed together with the other pending commands during the next update.
+    /// Fails instead of blocking when the queue is full, which happens when the ui isn't updated.
+    pub fn queue_message(&self, message: M::Message) -> Result<(), TrySendError<Command<M::Message>>> {
+        self.sender.sender.try_send(Command::Message(message))
+    }
+
+    /// Overrides the scale factor of the window for this ui, for example for uis that aren't shown in a window.
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        self.scale_factor = Some(scale_factor);
+    }
+
+    /// The scale factor override of this ui, if any.
+    pub fn scale_factor(&self) -> Option<f32> {
+        self.scale_factor
+    }
+
+    /// A sender for commands to this ui, for producers of async work outside of the model.
+    pub fn sender(&self) -> EventSender<M> {
+        self.sender.clone()
+    }
+
+    pub fn model(&self) -> &M {
+        &self.ui
+    }
+
+    /// Mutable access to the model. The view is rebuilt afterwards, so changes show up on screen.
+    pub fn model_mut(&mut self) -> &mut M {
+        &mut self.ui
+    }
+
+    /// Forces the view of the model to be rebuilt before the next draw.
+    /// This is only needed when the model was changed in a way pixel_widgets can't observe,
+    /// like through interior mutability or shared state read by `Model::view`.
+    pub fn rebuild(&mut self) {
+        // pixel_widgets drops the current view whenever the model is borrowed mutably
+        self.ui.deref_mut();
+    }
+
+    /// Whether the stylesheet of this ui, including its fonts, has been loaded.
+    /// Nothing is drawn until then, so apps can show their own splash screen in the meantime.
+    pub fn is_style_loaded(&self) -> bool {
+        self.style_loaded
+    }
+
+    /// Whether the ui has changed since it was last drawn. Uis are drawn at most once per update, and only when
+    /// they are dirty, so this tells which uis are actually redrawing.
+    pub fn is_dirty(&self) -> bool {
+        self.ui.needs_redraw()
+    }
+
+    /// Where the style this ui is currently using comes from.
+    pub fn style_source(&self) -> &style::StyleSource {
+        &self.style_source
+    }
+}
+
+#[cfg(feature = "inspector")]
+impl<M: Model + Send + Sync + bevy::reflect::Reflect> Ui<M> {
+    /// The model through bevy's reflection, for inspectors.
+    pub fn reflect_model(&self) -> &dyn bevy::reflect::Reflect {
+        self.model()
+    }
+
+    /// Mutable reflection of the model, so an inspector can edit its fields.
+    /// Like `model_mut`, the view is rebuilt afterwards.
+    pub fn reflect_model_mut(&mut self) -> &mut dyn bevy::reflect::Reflect {
+        self.model_mut()
+    }
+}
+
+impl Default for UiDraw {
+    fn default() -> Self {
+        Self {
+            vertices: None,
+            updates: Vec::new(),
+            commands: Vec::new(),
+            redrawn: false,
+            scale_factor: 1.0,
+            released_buffers: None,
         }
     }
 }
 
+impl Drop for UiDraw {
+    fn drop(&mut self) {
+        // only the render node can free the buffer, it picks it up on the next frame
+        if let (Some(buffer), Some(released_buffers)) = (self.vertices.take(), self.released_buffers.as_ref()) {
+            released_buffers.0.lock().unwrap().push(buffer);
+        }
+    }
+}
+
+impl<M: Model + Send + Sync> Drop for Ui<M> {
+    fn drop(&mut self) {
+        self.sender.alive.store(false, Ordering::Release);
+    }
+}
+
 impl<M: Model + Send + Sync> Deref for Ui<M> {
     type Target = pixel_widgets::Ui<M, EventSender<M>, DisabledLoader>;
 
@@ -118,3 +289,73 @@ impl Loader for DisabledLoader {
         unimplemented!("please load stylesheets using the bevy asset system");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{Counter, Message};
+
+    #[test]
+    fn sender_disconnects_when_ui_is_dropped() {
+        let ui = Ui::new(Counter::default());
+        let sender = ui.sender();
+        assert!(sender.is_connected());
+
+        drop(ui);
+        assert!(!sender.is_connected());
+    }
+
+    #[test]
+    fn queue_message_fails_when_full() {
+        let ui = Ui::new(Counter::default());
+        for _ in 0..COMMAND_QUEUE_CAPACITY {
+            assert!(ui.queue_message(Message::Up).is_ok());
+        }
+        assert!(matches!(ui.queue_message(Message::Up), Err(TrySendError::Full(_))));
+    }
+}
+
+#[cfg(all(test, feature = "inspector"))]
+mod inspector_tests {
+    use bevy::reflect::{Reflect, ReflectMut};
+    use pixel_widgets::tracker::ManagedState;
+    use pixel_widgets::widget::{self, IntoNode};
+
+    use super::*;
+    use crate::test_util::Message;
+
+    /// A button for every `value`, so edits in an inspector change what's drawn.
+    #[derive(Default, Reflect)]
+    struct Inspected {
+        value: i32,
+        #[reflect(ignore)]
+        state: ManagedState<String>,
+    }
+
+    impl Model for Inspected {
+        type Message = Message;
+
+        fn view(&mut self) -> widget::Node<Message> {
+            let mut state = self.state.tracker();
+            let mut column = widget::Column::new();
+            for i in 0..self.value {
+                column = column.push(widget::Button::new(state.get(&i.to_string()), widget::Column::new()));
+            }
+            column.into_node()
+        }
+    }
+
+    #[test]
+    fn reflected_edits_rebuild_the_view() {
+        let mut ui = Ui::new(Inspected::default());
+        ui.draw();
+        assert!(!ui.is_dirty());
+
+        match ui.reflect_model_mut().reflect_mut() {
+            ReflectMut::Struct(model) => model.field_mut("value").unwrap().apply(&5i32),
+            _ => panic!("the model is a struct"),
+        }
+        assert_eq!(ui.model().value, 5);
+        assert!(ui.is_dirty());
+    }
+}
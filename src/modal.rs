@@ -0,0 +1,72 @@
+use bevy::ecs::entity::{Entities, Entity};
+
+/// Stack of modal `Ui` entities.
+/// While it contains a live entity, only the topmost one receives input, all other uis are inert.
+#[derive(Debug, Clone, Default)]
+pub struct ModalStack {
+    stack: Vec<Entity>,
+}
+
+impl ModalStack {
+    /// Opens a modal on top of the stack.
+    pub fn push(&mut self, entity: Entity) {
+        self.remove(entity);
+        self.stack.push(entity);
+    }
+
+    /// Closes the topmost modal.
+    pub fn pop(&mut self) -> Option<Entity> {
+        self.stack.pop()
+    }
+
+    /// Closes a modal, wherever it is in the stack.
+    pub fn remove(&mut self, entity: Entity) {
+        self.stack.retain(|&e| e != entity);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    /// The topmost modal that hasn't been despawned.
+    pub(crate) fn top(&self, entities: &Entities) -> Option<Entity> {
+        self.stack.iter().rev().copied().find(|&e| entities.contains(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::world::World;
+
+    use super::*;
+    use crate::test_util::*;
+    use crate::Ui;
+
+    #[test]
+    fn top_skips_despawned_modals() {
+        let mut world = World::new();
+        let (bottom, top) = (world.spawn().id(), world.spawn().id());
+        let mut modals = ModalStack::default();
+        modals.push(bottom);
+        modals.push(top);
+        assert_eq!(modals.top(world.entities()), Some(top));
+
+        world.despawn(top);
+        assert_eq!(modals.top(world.entities()), Some(bottom));
+    }
+
+    #[test]
+    fn only_top_modal_receives_input() {
+        let mut app = window_app();
+        let base = spawn_counter(&mut app.world);
+        let modal = spawn_counter(&mut app.world);
+        app.world.get_resource_mut::<ModalStack>().unwrap().push(modal);
+
+        click_up(&mut app, 600.0);
+        app.update();
+
+        let value = |entity| app.world.get::<Ui<Counter>>(entity).unwrap().model().value;
+        assert_eq!(value(base), 0);
+        assert_eq!(value(modal), 1);
+    }
+}
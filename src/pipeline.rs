@@ -4,52 +4,292 @@ use bevy::render::pipeline::*;
 use bevy::render::shader::{ShaderStage, ShaderStages};
 use bevy::render::texture::TextureFormat;
 
+use crate::plugin::UiDepth;
+
+/// Handle of the base UI pipeline that `UiPlugin` creates.
+/// The pipeline is drawn through the specialization returned by `ui_pipeline_specialization`.
 pub const UI_PIPELINE_HANDLE: HandleUntyped =
     HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 1132409877698723298);
 
-pub fn build_ui_pipeline(shaders: &mut Assets<Shader>) -> PipelineDescriptor {
+/// Same as the UI pipeline, but renders the edges of the triangles only.
+pub const UI_WIREFRAME_PIPELINE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 6620351383948127290);
+
+/// Same as the UI pipeline, but with the alpha blending of `UiBlend::STRAIGHT_ALPHA`, for texture targets.
+/// Their alpha ends up in the texture instead of being thrown away by the window, so it has to stay correct.
+pub const UI_TARGET_PIPELINE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 2894405927307321846);
+
+/// Renders the UI as a wireframe when enabled, to show how pixel_widgets triangulates the widgets.
+/// This requires the `NonFillPolygonMode` feature to be enabled in the `WgpuOptions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UiWireframe(pub bool);
+
+/// Draws a single ui with a different pipeline than the UI pipeline, for example one with a CRT effect shader.
+/// The pipeline has to use the same vertex layout and bind groups as the UI shaders, usually only the fragment
+/// shader is replaced. The `UiClip` uniforms in set 2 are optional, without them clip rects keep hard edges.
+/// It's specialized like the UI pipeline once it has been added to `Assets<PipelineDescriptor>`,
+/// until then the ui is drawn with the UI pipeline. The wireframe pipeline takes precedence while it's enabled.
+#[derive(Debug, Clone)]
+pub struct UiCustomPipeline(pub Handle<PipelineDescriptor>);
+
+/// How the UI is blended with what has been rendered before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UiBlend {
+    pub color: BlendState,
+    pub alpha: BlendState,
+}
+
+impl UiBlend {
+    /// Blends colors by their alpha and adds up the alpha values. Fine for drawing straight onto the screen.
+    pub const DEFAULT: Self = Self {
+        color: BlendState {
+            src_factor: BlendFactor::SrcAlpha,
+            dst_factor: BlendFactor::OneMinusSrcAlpha,
+            operation: BlendOperation::Add,
+        },
+        alpha: BlendState {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add,
+        },
+    };
+
+    /// Straight alpha blending for both color and alpha, so the alpha of transparent targets stays correct.
+    pub const STRAIGHT_ALPHA: Self = Self {
+        color: BlendState {
+            src_factor: BlendFactor::SrcAlpha,
+            dst_factor: BlendFactor::OneMinusSrcAlpha,
+            operation: BlendOperation::Add,
+        },
+        alpha: BlendState {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::OneMinusSrcAlpha,
+            operation: BlendOperation::Add,
+        },
+    };
+
+    /// Blending for colors that are already multiplied by their alpha, like those of premultiplied textures.
+    pub const PREMULTIPLIED_ALPHA: Self = Self {
+        color: BlendState {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::OneMinusSrcAlpha,
+            operation: BlendOperation::Add,
+        },
+        alpha: BlendState {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::OneMinusSrcAlpha,
+            operation: BlendOperation::Add,
+        },
+    };
+}
+
+impl Default for UiBlend {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Handles of the UI shaders. Setting a new `Shader` on one of these in `Assets<Shader>` at runtime
+/// recompiles the UI pipeline with it, which is useful for iterating on the shaders without restarting.
+pub const UI_VERTEX_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 8046294791236105743);
+pub const UI_FRAGMENT_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 3572870425163520941);
+
+/// The specialization the UI pipeline is compiled with, matching the layout of pixel_widgets' `Vertex`.
+/// Returns `None` until `UiPlugin` has added the pipeline at `UI_PIPELINE_HANDLE`.
+/// The sample count is left at 1, passes with multisampling have to set their own.
+///
+/// Custom render graph nodes can draw with the UI pipeline by compiling it with this specialization:
+/// ```
+/// use bevy::prelude::*;
+/// use bevy::render::pipeline::PipelineDescriptor;
+/// use bevy_pixel_widgets::prelude::*;
+///
+/// fn ui_pipeline(pipelines: &Assets<PipelineDescriptor>) -> Option<&PipelineDescriptor> {
+///     pipelines.get(&UI_PIPELINE_HANDLE.clone().typed::<PipelineDescriptor>())
+/// }
+///
+/// let mut app = App::build();
+/// app.add_plugin(bevy::core::CorePlugin)
+///     .add_plugin(bevy::asset::AssetPlugin)
+///     .add_asset::<PipelineDescriptor>();
+/// let pipelines = app.world().get_resource::<Assets<PipelineDescriptor>>().unwrap();
+///
+/// // without the UiPlugin there is no UI pipeline to specialize
+/// assert!(ui_pipeline(pipelines).is_none());
+/// assert!(ui_pipeline_specialization(pipelines).is_none());
+/// ```
+pub fn ui_pipeline_specialization(pipelines: &Assets<PipelineDescriptor>) -> Option<PipelineSpecialization> {
+    // the specialization overrides the topology of the descriptor, so carry over the one the plugin configured
+    let primitive_topology = pipelines
+        .get(&UI_PIPELINE_HANDLE.clone().typed::<PipelineDescriptor>())?
+        .primitive
+        .topology;
+
+    Some(PipelineSpecialization {
+        vertex_buffer_layout: VertexBufferLayout {
+            name: Default::default(),
+            stride: 36,
+            step_mode: Default::default(),
+            attributes: vec![
+                VertexAttribute {
+                    name: "Vertex_Position".into(),
+                    offset: 0,
+                    format: VertexFormat::Float2,
+                    shader_location: 0,
+                },
+                VertexAttribute {
+                    name: "Vertex_Uv".into(),
+                    offset: 8,
+                    format: VertexFormat::Float2,
+                    shader_location: 1,
+                },
+                VertexAttribute {
+                    name: "Vertex_Color".into(),
+                    offset: 16,
+                    format: VertexFormat::Float4,
+                    shader_location: 2,
+                },
+                VertexAttribute {
+                    name: "Vertex_Mode".into(),
+                    offset: 32,
+                    format: VertexFormat::Uint,
+                    shader_location: 3,
+                },
+            ],
+        },
+        primitive_topology,
+        ..PipelineSpecialization::default()
+    })
+}
+
+/// The compiled UI pipeline, once the render node has used it for the first time.
+/// The render node compiles it for the sample count of `msaa`, like bevy's own pipelines.
+pub fn specialized_ui_pipeline(
+    pipeline_compiler: &PipelineCompiler,
+    pipelines: &Assets<PipelineDescriptor>,
+    msaa: &Msaa,
+) -> Option<Handle<PipelineDescriptor>> {
+    let specialization = PipelineSpecialization {
+        sample_count: msaa.samples,
+        ..ui_pipeline_specialization(pipelines)?
+    };
+    pipeline_compiler.get_specialized_pipeline(&UI_PIPELINE_HANDLE.clone().typed(), &specialization)
+}
+
+pub fn build_ui_pipeline(
+    shaders: &mut Assets<Shader>,
+    depth: UiDepth,
+    topology: PrimitiveTopology,
+    blend: UiBlend,
+) -> PipelineDescriptor {
     PipelineDescriptor {
         primitive: PrimitiveState {
-            topology: PrimitiveTopology::TriangleList,
+            topology,
             strip_index_format: None,
             front_face: FrontFace::Ccw,
             cull_mode: CullMode::None,
             polygon_mode: PolygonMode::Fill,
         },
-        depth_stencil: Some(DepthStencilState {
-            format: TextureFormat::Depth32Float,
-            depth_write_enabled: true,
-            depth_compare: CompareFunction::LessEqual,
-            stencil: StencilState {
-                front: StencilFaceState::IGNORE,
-                back: StencilFaceState::IGNORE,
-                read_mask: 0,
-                write_mask: 0,
-            },
-            bias: DepthBiasState {
-                constant: 0,
-                slope_scale: 0.0,
-                clamp: 0.0,
-            },
-            clamp_depth: false
-        }),
+        depth_stencil: match depth {
+            UiDepth::Test => Some(ui_depth_stencil_state(CompareFunction::LessEqual, true)),
+            UiDepth::Overlay => Some(ui_depth_stencil_state(CompareFunction::Always, false)),
+            UiDepth::Disabled => None,
+        },
         color_target_states: vec![ColorTargetState {
             format: TextureFormat::Bgra8UnormSrgb,
-            color_blend: BlendState {
-                src_factor: BlendFactor::SrcAlpha,
-                dst_factor: BlendFactor::OneMinusSrcAlpha,
-                operation: BlendOperation::Add,
-            },
-            alpha_blend: BlendState {
-                src_factor: BlendFactor::One,
-                dst_factor: BlendFactor::One,
-                operation: BlendOperation::Add,
-            },
+            color_blend: blend.color,
+            alpha_blend: blend.alpha,
             write_mask: ColorWrite::ALL,
         }],
         ..PipelineDescriptor::new(ShaderStages {
-            vertex: shaders.add(Shader::from_glsl(ShaderStage::Vertex, include_str!("ui.vert"))),
-            fragment: Some(shaders.add(Shader::from_glsl(ShaderStage::Fragment, include_str!("ui.frag")))),
+            vertex: shaders.set(
+                UI_VERTEX_SHADER_HANDLE,
+                Shader::from_glsl(ShaderStage::Vertex, include_str!("ui.vert")),
+            ),
+            fragment: Some(shaders.set(
+                UI_FRAGMENT_SHADER_HANDLE,
+                Shader::from_glsl(ShaderStage::Fragment, include_str!("ui.frag")),
+            )),
         })
     }
 }
+
+fn ui_depth_stencil_state(depth_compare: CompareFunction, depth_write_enabled: bool) -> DepthStencilState {
+    DepthStencilState {
+        format: TextureFormat::Depth32Float,
+        depth_write_enabled,
+        depth_compare,
+        stencil: StencilState {
+            front: StencilFaceState::IGNORE,
+            back: StencilFaceState::IGNORE,
+            read_mask: 0,
+            write_mask: 0,
+        },
+        bias: DepthBiasState {
+            constant: 0,
+            slope_scale: 0.0,
+            clamp: 0.0,
+        },
+        clamp_depth: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::AssetPlugin;
+    use bevy::core::CorePlugin;
+    use bevy::render::pass::LoadOp;
+
+    use super::*;
+    use crate::plugin::window_pass_descriptor;
+
+    #[test]
+    fn overlay_always_passes_the_depth_test_without_writing() {
+        let mut app = App::build();
+        app.add_plugin(CorePlugin).add_plugin(AssetPlugin).add_asset::<Shader>();
+        let mut shaders = app.world_mut().get_resource_mut::<Assets<Shader>>().unwrap();
+
+        let pipeline = build_ui_pipeline(
+            &mut shaders,
+            UiDepth::Overlay,
+            PrimitiveTopology::TriangleList,
+            UiBlend::default(),
+        );
+        let depth_stencil = pipeline.depth_stencil.unwrap();
+        assert_eq!(depth_stencil.depth_compare, CompareFunction::Always);
+        assert!(!depth_stencil.depth_write_enabled);
+
+        // the scene depth is kept, not cleared
+        let pass = window_pass_descriptor(&Msaa::default(), UiDepth::Overlay, LoadOp::Load);
+        let depth_ops = pass.depth_stencil_attachment.unwrap().depth_ops.unwrap();
+        assert!(matches!(depth_ops.load, LoadOp::Load));
+    }
+
+    #[test]
+    fn topology_reaches_the_pipeline_and_its_specialization() {
+        let mut app = App::build();
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_asset::<Shader>()
+            .add_asset::<PipelineDescriptor>();
+        let mut shaders = app.world_mut().get_resource_mut::<Assets<Shader>>().unwrap();
+        let pipeline = build_ui_pipeline(
+            &mut shaders,
+            UiDepth::Test,
+            PrimitiveTopology::TriangleStrip,
+            UiBlend::default(),
+        );
+        assert_eq!(pipeline.primitive.topology, PrimitiveTopology::TriangleStrip);
+
+        let mut pipelines = app
+            .world_mut()
+            .get_resource_mut::<Assets<PipelineDescriptor>>()
+            .unwrap();
+        pipelines.set_untracked(UI_PIPELINE_HANDLE, pipeline);
+        let specialization = ui_pipeline_specialization(&pipelines).unwrap();
+        assert_eq!(specialization.primitive_topology, PrimitiveTopology::TriangleStrip);
+    }
+}
@@ -1,17 +1,27 @@
 use std::ops::Range;
 use std::sync::{Arc, Mutex};
 
+use bevy::diagnostic::Diagnostics;
 use bevy::prelude::*;
 use bevy::render::pass::*;
 use bevy::render::pipeline::*;
 use bevy::render::render_graph::{CommandQueue, Node, ResourceSlotInfo, ResourceSlots, SystemNode};
 use bevy::render::renderer::RenderContext;
+use bevy::render::texture::{FilterMode, TextureFormat, TextureUsage, SAMPLER_ASSET_INDEX, TEXTURE_ASSET_INDEX};
+use zerocopy::AsBytes;
 
-use crate::pipeline::UI_PIPELINE_HANDLE;
-use crate::style::Stylesheet;
+use crate::diagnostics::UiDiagnostics;
+use crate::filter::{UiColorFilter, UiGamma};
+use crate::pipeline::{
+    ui_pipeline_specialization, UiCustomPipeline, UiWireframe, UI_PIPELINE_HANDLE, UI_TARGET_PIPELINE_HANDLE,
+    UI_WIREFRAME_PIPELINE_HANDLE,
+};
+use crate::style::{StyleOverride, Stylesheet};
+use crate::target::{PassTarget, SharedTargetTextures, TargetTextures, UiRenderTarget, UiTextureTarget, UiWindow};
 
 use super::*;
-use bevy::utils::HashMap;
+use bevy::asset::HandleId;
+use bevy::utils::{HashMap, HashSet};
 
 pub struct UiNode {
     command_queue: CommandQueue,
@@ -21,6 +31,9 @@ pub struct UiNode {
     color_attachment_input_indices: Vec<Option<usize>>,
     color_resolve_target_indices: Vec<Option<usize>>,
     depth_stencil_attachment_input_index: Option<usize>,
+    // the window or texture this node draws into
+    target: PassTarget,
+    target_textures: SharedTargetTextures,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -64,6 +77,31 @@ impl Node for UiNode {
     ) {
         self.command_queue.execute(render_context);
 
+        // the attachments no longer match after a runtime change of the sample count, render_ui logs that once
+        let msaa = world.get_resource::<Msaa>();
+        if msaa.map_or(false, |msaa| msaa.samples != self.descriptor.sample_count) {
+            return;
+        }
+
+        // texture targets draw into the textures that render_ui created for them
+        if let PassTarget::Texture(_) = self.target {
+            let textures = if let Some(textures) = *self.target_textures.lock().unwrap() {
+                textures
+            } else {
+                return;
+            };
+            let color_attachment = &mut self.descriptor.color_attachments[0];
+            if let Some(sampled) = textures.sampled {
+                color_attachment.attachment = TextureAttachment::Id(sampled);
+                color_attachment.resolve_target = Some(TextureAttachment::Id(textures.color));
+            } else {
+                color_attachment.attachment = TextureAttachment::Id(textures.color);
+            }
+            if let Some(attachment) = self.descriptor.depth_stencil_attachment.as_mut() {
+                attachment.attachment = TextureAttachment::Id(textures.depth.unwrap());
+            }
+        }
+
         let render_resource_bindings = world.get_resource::<RenderResourceBindings>().unwrap();
         let pipelines = world.get_resource::<Assets<PipelineDescriptor>>().unwrap();
 
@@ -135,7 +173,21 @@ impl SystemNode for UiNode {
             config.0 = Some(State {
                 command_queue: self.command_queue.clone(),
                 command_buffer: self.command_buffer.clone(),
-                sampler_id: None,
+                sampler: None,
+                uniforms: None,
+                textures: Default::default(),
+                staging_buffers: Vec::new(),
+                retired_textures: Vec::new(),
+                retired_samplers: Vec::new(),
+                frame: 0,
+                white_texture: None,
+                bind_groups: Default::default(),
+                clips: Default::default(),
+                sample_count: self.descriptor.sample_count,
+                sample_count_changed: false,
+                depth: self.descriptor.depth_stencil_attachment.is_some(),
+                target: self.target.clone(),
+                target_textures: self.target_textures.clone(),
             });
         });
         Box::new(system)
@@ -143,7 +195,8 @@ impl SystemNode for UiNode {
 }
 
 impl UiNode {
-    pub fn new(descriptor: PassDescriptor) -> Self {
+    /// A node that draws the uis of `target` with `descriptor`.
+    pub fn new(descriptor: PassDescriptor, target: PassTarget) -> Self {
         let mut inputs = Vec::new();
         let mut color_attachment_input_indices = Vec::new();
         let mut color_resolve_target_indices = Vec::new();
@@ -179,6 +232,8 @@ impl UiNode {
             color_attachment_input_indices,
             color_resolve_target_indices,
             depth_stencil_attachment_input_index,
+            target,
+            target_textures: Default::default(),
         }
     }
 }
@@ -187,7 +242,335 @@ impl UiNode {
 struct State {
     command_queue: CommandQueue,
     command_buffer: Arc<Mutex<Vec<RenderCommand>>>,
-    sampler_id: Option<SamplerId>,
+    sampler: Option<(SamplerId, bool, FilterMode)>,
+    uniforms: Option<(BufferId, Uniforms)>,
+    // texture ids are only unique within a stylesheet, so they are namespaced by its handle or override
+    textures: HashMap<(TextureNamespace, usize), TextureId>,
+    // staging buffers of last frame's uploads, the copies have been executed by the node by now
+    staging_buffers: Vec<BufferId>,
+    // textures that were replaced or are no longer used, with the frame in which that happened
+    retired_textures: Vec<(u64, TextureId)>,
+    // samplers that were replaced after a settings change, with the frame in which that happened
+    retired_samplers: Vec<(u64, SamplerId)>,
+    frame: u64,
+    white_texture: Option<TextureId>,
+    // bind groups of textures together with the current sampler
+    bind_groups: HashMap<TextureId, CachedBindGroup>,
+    // uniforms and bind groups of the clip rects that were drawn with this frame, by the bits of their uniforms
+    clips: HashMap<[u32; 5], CachedClip>,
+    // sample count of the pass, which is fixed when the plugin builds it
+    sample_count: u32,
+    // whether the `Msaa` sample count was changed since, which has been logged then
+    sample_count_changed: bool,
+    // whether the pass has a depth attachment
+    depth: bool,
+    target: PassTarget,
+    target_textures: SharedTargetTextures,
+}
+
+impl State {
+    /// A 1x1 white texture for color only draws, which still need a texture to be bound.
+    /// It's created on first use and then kept around.
+    fn white_texture(&mut self, render_resource_context: &dyn RenderResourceContext) -> TextureId {
+        if let Some(texture) = self.white_texture {
+            return texture;
+        }
+
+        let size = Extent3d {
+            width: 1,
+            height: 1,
+            depth: 1,
+        };
+        let texture = render_resource_context.create_texture(TextureDescriptor {
+            size,
+            ..TextureDescriptor::default()
+        });
+        let (data, bytes_per_row) = pad_rows(vec![255; 4], 1);
+        let staging_buffer = render_resource_context.create_buffer_with_data(
+            BufferInfo {
+                size: data.len(),
+                buffer_usage: BufferUsage::COPY_SRC,
+                mapped_at_creation: false,
+            },
+            data.as_slice(),
+        );
+        self.command_queue
+            .copy_buffer_to_texture(staging_buffer, 0, bytes_per_row, texture, [0; 3], 0, size);
+        self.staging_buffers.push(staging_buffer);
+
+        self.white_texture = Some(texture);
+        texture
+    }
+
+    /// The bind group that draws with `texture`. It's created on first use and then reused, until the texture is
+    /// freed or the sampler is replaced.
+    fn texture_bind_group(
+        &mut self,
+        texture: TextureId,
+        sampler: SamplerId,
+        descriptor: BindGroupDescriptorId,
+        render_resource_context: &dyn RenderResourceContext,
+    ) -> BindGroupId {
+        let frame = self.frame;
+        let cached = self.bind_groups.entry(texture).or_insert_with(|| CachedBindGroup {
+            bind_group: BindGroup::build()
+                .add_texture(0, texture)
+                .add_sampler(1, sampler)
+                .finish(),
+            used: None,
+        });
+        // bevy frees bind groups that weren't set in any pass during the last frame,
+        // so only those that are new or weren't drawn with last frame have to be created again
+        if cached.used.map_or(true, |used| used + 1 < frame) {
+            render_resource_context.create_bind_group(descriptor, &cached.bind_group);
+        }
+        cached.used = Some(frame);
+        cached.bind_group.id
+    }
+
+    /// The bind group of the `UiClip` uniforms of `clip`. It's created on first use and then reused, as long as it's
+    /// drawn with every frame.
+    fn clip_bind_group(
+        &mut self,
+        clip: ClipUniforms,
+        descriptor: BindGroupDescriptorId,
+        render_resource_context: &dyn RenderResourceContext,
+    ) -> BindGroupId {
+        let frame = self.frame;
+        let cached = self.clips.entry(clip.key()).or_insert_with(|| {
+            let buffer = render_resource_context.create_buffer_with_data(
+                BufferInfo {
+                    size: std::mem::size_of::<ClipUniforms>(),
+                    buffer_usage: BufferUsage::UNIFORM,
+                    mapped_at_creation: false,
+                },
+                clip.as_bytes(),
+            );
+            let bind_group = BindGroup::build()
+                .add_binding(
+                    0,
+                    RenderResourceBinding::Buffer {
+                        buffer,
+                        range: 0..std::mem::size_of::<ClipUniforms>() as u64,
+                        dynamic_index: None,
+                    },
+                )
+                .finish();
+            render_resource_context.create_bind_group(descriptor, &bind_group);
+            CachedClip {
+                buffer,
+                bind_group,
+                used: frame,
+            }
+        });
+        cached.used = frame;
+        cached.bind_group.id
+    }
+
+    /// The textures of the texture target, which are created on first use.
+    /// The color texture becomes the render resource of the target's handle, so it can be sampled by materials.
+    /// With multisampling, the uis are drawn into a multisampled texture that is resolved into the color texture.
+    fn target_textures(
+        &self,
+        target: &UiTextureTarget,
+        render_resource_context: &dyn RenderResourceContext,
+    ) -> TargetTextures {
+        let mut target_textures = self.target_textures.lock().unwrap();
+        if let Some(textures) = *target_textures {
+            return textures;
+        }
+
+        let size = Extent3d {
+            width: target.width,
+            height: target.height,
+            depth: 1,
+        };
+        let color = render_resource_context.create_texture(TextureDescriptor {
+            size,
+            // the format of the UI pipeline's color target
+            format: TextureFormat::Bgra8UnormSrgb,
+            usage: TextureUsage::SAMPLED | TextureUsage::OUTPUT_ATTACHMENT,
+            ..TextureDescriptor::default()
+        });
+        let sampled = if self.sample_count > 1 {
+            Some(render_resource_context.create_texture(TextureDescriptor {
+                size,
+                sample_count: self.sample_count,
+                format: TextureFormat::Bgra8UnormSrgb,
+                usage: TextureUsage::OUTPUT_ATTACHMENT,
+                ..TextureDescriptor::default()
+            }))
+        } else {
+            None
+        };
+        let depth = if self.depth {
+            Some(render_resource_context.create_texture(TextureDescriptor {
+                size,
+                sample_count: self.sample_count,
+                format: TextureFormat::Depth32Float,
+                usage: TextureUsage::OUTPUT_ATTACHMENT,
+                ..TextureDescriptor::default()
+            }))
+        } else {
+            None
+        };
+        let sampler = render_resource_context.create_sampler(&SamplerDescriptor {
+            mag_filter: target.filter,
+            min_filter: target.filter,
+            ..SamplerDescriptor::default()
+        });
+        render_resource_context.set_asset_resource(
+            &target.texture,
+            RenderResourceId::Texture(color),
+            TEXTURE_ASSET_INDEX,
+        );
+        render_resource_context.set_asset_resource(
+            &target.texture,
+            RenderResourceId::Sampler(sampler),
+            SAMPLER_ASSET_INDEX,
+        );
+
+        let textures = TargetTextures { color, sampled, depth };
+        *target_textures = Some(textures);
+        textures
+    }
+}
+
+/// A texture bind group, with the frame in which it was last drawn with.
+struct CachedBindGroup {
+    bind_group: BindGroup,
+    used: Option<u64>,
+}
+
+/// The uniform buffer and bind group of a clip rect, with the frame in which it was last drawn with.
+struct CachedClip {
+    buffer: BufferId,
+    bind_group: BindGroup,
+    used: u64,
+}
+
+/// Vertex buffers of dropped `UiDraw` components, freed by `render_ui`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ReleasedBuffers(pub(crate) Arc<Mutex<Vec<BufferId>>>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum TextureNamespace {
+    Stylesheet(HandleId),
+    Override(u64),
+}
+
+/// All texture uploads of a stylesheet, packed into a single staging buffer.
+#[derive(Default)]
+struct TextureUploads {
+    data: Vec<u8>,
+    copies: Vec<TextureCopy>,
+    // merge uploads of vertically adjacent regions into a single copy
+    coalesce: bool,
+}
+
+struct TextureCopy {
+    offset: u64,
+    bytes_per_row: u32,
+    texture: TextureId,
+    origin: [u32; 3],
+    mip_level: u32,
+    size: Extent3d,
+}
+
+/// Settings that control how the UI is rendered.
+#[derive(Debug, Clone)]
+pub struct UiRenderSettings {
+    /// Generate mipmaps for stylesheet images, so they stay smooth when drawn much smaller than their source.
+    /// Atlas textures never get mipmaps, because they are updated in parts.
+    pub mipmaps: bool,
+    /// Flip the UI vertically, for render targets whose normalized device coordinates have y pointing down.
+    pub flip_y: bool,
+    /// Number of frames a replaced texture is kept alive, because frames that are still in flight may use it.
+    pub texture_release_delay: u64,
+    /// How textures are filtered when they are magnified or minified.
+    /// Use `FilterMode::Nearest` to keep pixel fonts and pixel art crisp.
+    pub filter: FilterMode,
+    /// Don't redraw uis while none of the windows has focus, to save power when the app is in the background.
+    /// Input still reaches the uis, and they are redrawn once a window has focus again.
+    pub pause_while_unfocused: bool,
+    /// Merge texture uploads of vertically adjacent regions with the same width into a single copy,
+    /// like the rows of an atlas that pixel_widgets fills in parts.
+    pub coalesce_texture_uploads: bool,
+    /// Width in physical pixels over which content fades out towards the edges of its clip rect, for smooth edges
+    /// of scrolled content. The scissor rect still cuts off everything outside of the clip rect.
+    /// With 0, clip rects have hard edges.
+    pub clip_feather: f32,
+}
+
+impl Default for UiRenderSettings {
+    fn default() -> Self {
+        Self {
+            mipmaps: false,
+            flip_y: false,
+            texture_release_delay: 2,
+            filter: FilterMode::Linear,
+            pause_while_unfocused: false,
+            coalesce_texture_uploads: true,
+            clip_feather: 0.0,
+        }
+    }
+}
+
+/// Layout of the `UiUniforms` block in `ui.frag`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, AsBytes)]
+struct Uniforms {
+    color_filter: [f32; 16],
+    gamma: f32,
+    flip_y: f32,
+    // std140 rounds the block size up to a multiple of 16 bytes
+    _padding: [f32; 2],
+}
+
+/// Layout of the `UiClip` block in `ui.frag`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, AsBytes)]
+struct ClipUniforms {
+    // left, top, right and bottom in physical pixels
+    rect: [f32; 4],
+    feather: f32,
+    _padding: [f32; 3],
+}
+
+impl ClipUniforms {
+    /// The uniforms of a clip rect within a target of `width` by `height` physical pixels.
+    /// Edges that lie on the border of the target are moved outwards by the feather width,
+    /// so content isn't faded out at the border of the window.
+    fn new([left, top, right, bottom]: [f32; 4], feather: f32, (width, height): (f32, f32)) -> Self {
+        let extend = |edge: f32, border: f32, outwards: f32| {
+            if edge == border {
+                border + outwards
+            } else {
+                edge
+            }
+        };
+        Self {
+            rect: [
+                extend(left, 0.0, -feather),
+                extend(top, 0.0, -feather),
+                extend(right, width, feather),
+                extend(bottom, height, feather),
+            ],
+            feather,
+            _padding: [0.0; 3],
+        }
+    }
+
+    fn key(&self) -> [u32; 5] {
+        let [left, top, right, bottom] = self.rect;
+        [
+            left.to_bits(),
+            top.to_bits(),
+            right.to_bits(),
+            bottom.to_bits(),
+            self.feather.to_bits(),
+        ]
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -197,12 +580,87 @@ fn render_ui(
     mut shaders: ResMut<Assets<Shader>>,
     mut pipeline_compiler: ResMut<PipelineCompiler>,
     mut render_resource_bindings: ResMut<RenderResourceBindings>,
-    mut stylesheets: ResMut<Assets<Stylesheet>>,
-    render_resource_context: Res<Box<dyn RenderResourceContext>>,
+    stylesheets: Res<Assets<Stylesheet>>,
+    render_resource_context: Option<Res<Box<dyn RenderResourceContext>>>,
     windows: Res<Windows>,
-    mut query: Query<(&mut UiDraw, &Handle<Stylesheet>)>,
+    msaa: Res<Msaa>,
+    color_filter: Res<UiColorFilter>,
+    gamma: Res<UiGamma>,
+    render_settings: Res<UiRenderSettings>,
+    released_buffers: Res<ReleasedBuffers>,
+    wireframe: Res<UiWireframe>,
+    diagnostics: Option<ResMut<Diagnostics>>,
+    mut query: Query<(
+        &mut UiDraw,
+        Option<&Handle<Stylesheet>>,
+        Option<&StyleOverride>,
+        Option<&UiLayer>,
+        Option<&UiCustomPipeline>,
+        Option<&UiRenderTarget>,
+        Option<&UiWindow>,
+    )>,
 ) {
-    let window = windows.get_primary().unwrap();
+    // the render resource context may not exist yet during the first frames
+    let render_resource_context = if let Some(context) = render_resource_context {
+        context
+    } else {
+        return;
+    };
+    // uis are drawn at the size of the texture target, or of the window
+    let (target_width, target_height) = match &state.target {
+        PassTarget::Texture(target) => {
+            state.target_textures(target, &**render_resource_context);
+            (target.width, target.height)
+        }
+        PassTarget::Window(id) => match windows.get(*id) {
+            Some(window) => (window.physical_width(), window.physical_height()),
+            None => return,
+        },
+    };
+    if msaa.samples != state.sample_count {
+        if !state.sample_count_changed {
+            log::error!(
+                "the Msaa sample count was changed from {} to {}, but it can't be changed after the UiPlugin has \
+                 been added. uis are no longer drawn",
+                state.sample_count,
+                msaa.samples
+            );
+            state.sample_count_changed = true;
+        }
+        state.command_buffer.lock().unwrap().clear();
+        return;
+    }
+
+    for buffer in std::mem::take(&mut state.staging_buffers) {
+        render_resource_context.remove_buffer(buffer);
+    }
+
+    state.frame += 1;
+    let (frame, delay) = (state.frame, render_settings.texture_release_delay);
+    let State {
+        retired_textures,
+        retired_samplers,
+        bind_groups,
+        ..
+    } = &mut *state;
+    retired_textures.retain(|&(retired, texture)| {
+        frame - retired <= delay || {
+            bind_groups.remove(&texture);
+            render_resource_context.remove_texture(texture);
+            false
+        }
+    });
+    retired_samplers.retain(|&(retired, sampler)| {
+        frame - retired <= delay || {
+            render_resource_context.remove_sampler(sampler);
+            false
+        }
+    });
+
+    // vertex buffers of despawned uis
+    for buffer in released_buffers.0.lock().unwrap().drain(..) {
+        render_resource_context.remove_buffer(buffer);
+    }
 
     let mut draw: Vec<RenderCommand> = {
         let mut command_buffer = state.command_buffer.lock().unwrap();
@@ -210,72 +668,149 @@ fn render_ui(
         std::mem::replace(&mut command_buffer, Vec::new())
     };
 
-    let sampler_id = *state
-        .sampler_id
-        .get_or_insert_with(|| render_resource_context.create_sampler(&SamplerDescriptor::default()));
+    if state.sampler.map_or(true, |(_, mipmaps, filter)| {
+        mipmaps != render_settings.mipmaps || filter != render_settings.filter
+    }) {
+        let sampler = render_resource_context.create_sampler(&SamplerDescriptor {
+            mag_filter: render_settings.filter,
+            min_filter: render_settings.filter,
+            mipmap_filter: if render_settings.mipmaps {
+                FilterMode::Linear
+            } else {
+                FilterMode::Nearest
+            },
+            ..SamplerDescriptor::default()
+        });
+        // the cached bind groups all refer to the old sampler
+        state.bind_groups.clear();
+        if let Some((old_sampler, _, _)) =
+            state
+                .sampler
+                .replace((sampler, render_settings.mipmaps, render_settings.filter))
+        {
+            // frames that are still in flight may sample with it, just like with replaced textures
+            state.retired_samplers.push((frame, old_sampler));
+        }
+    }
+    let (sampler_id, _, _) = state.sampler.unwrap();
 
+    let typed_handle = if wireframe.0 {
+        UI_WIREFRAME_PIPELINE_HANDLE.clone().typed()
+    } else if let PassTarget::Texture(_) = state.target {
+        UI_TARGET_PIPELINE_HANDLE.clone().typed()
+    } else {
+        UI_PIPELINE_HANDLE.clone().typed()
+    };
     let specialization = PipelineSpecialization {
-        vertex_buffer_layout: VertexBufferLayout {
-            name: Default::default(),
-            stride: 36,
-            step_mode: Default::default(),
-            attributes: vec![
-                VertexAttribute {
-                    name: "Vertex_Position".into(),
-                    offset: 0,
-                    format: VertexFormat::Float2,
-                    shader_location: 0,
-                },
-                VertexAttribute {
-                    name: "Vertex_Uv".into(),
-                    offset: 8,
-                    format: VertexFormat::Float2,
-                    shader_location: 1,
-                },
-                VertexAttribute {
-                    name: "Vertex_Color".into(),
-                    offset: 16,
-                    format: VertexFormat::Float4,
-                    shader_location: 2,
-                },
-                VertexAttribute {
-                    name: "Vertex_Mode".into(),
-                    offset: 32,
-                    format: VertexFormat::Uint,
-                    shader_location: 3,
-                },
-            ],
-        },
-        ..PipelineSpecialization::default()
+        sample_count: state.sample_count,
+        ..ui_pipeline_specialization(&pipelines).expect("the UiPlugin adds the UI pipeline")
     };
 
-    let typed_handle = UI_PIPELINE_HANDLE.clone().typed();
-    let pipeline =
-        if let Some(pipeline) = pipeline_compiler.get_specialized_pipeline(&typed_handle, &specialization) {
+    // custom pipelines of individual uis, those that haven't been added yet are skipped
+    let mut custom_handles = Vec::new();
+    if !wireframe.0 {
+        for (_, _, _, _, custom_pipeline, ..) in query.iter_mut() {
+            if let Some(UiCustomPipeline(handle)) = custom_pipeline {
+                if pipelines.get(handle).is_some() {
+                    custom_handles.push(handle.clone_weak());
+                }
+            }
+        }
+    }
+
+    let mut specialize = |handle: &Handle<PipelineDescriptor>| {
+        if let Some(pipeline) = pipeline_compiler.get_specialized_pipeline(handle, &specialization) {
             pipeline
         } else {
             pipeline_compiler.compile_pipeline(
                 &**render_resource_context,
                 &mut pipelines,
                 &mut shaders,
-                &typed_handle,
+                handle,
                 &specialization,
             )
-        };
+        }
+    };
+    let pipeline = specialize(&typed_handle);
+    // specialized custom pipelines by the id of their unspecialized handle
+    let custom_pipelines = custom_handles
+        .iter()
+        .map(|handle| (handle.id, specialize(handle)))
+        .collect::<HashMap<_, _>>();
 
     let pipeline_descriptor = pipelines.get(&pipeline).unwrap();
     let bind_group_descriptor = pipeline_descriptor.get_layout().unwrap().get_bind_group(0).unwrap();
 
+    let uniforms = Uniforms {
+        color_filter: color_filter.0.to_cols_array(),
+        // a gamma of 0 or below would divide by zero or invert the colors in the shader
+        gamma: gamma.0.max(f32::EPSILON),
+        flip_y: if render_settings.flip_y { 1.0 } else { 0.0 },
+        _padding: [0.0; 2],
+    };
+    if state.uniforms.map_or(true, |(_, current)| current != uniforms) {
+        let buffer = render_resource_context.create_buffer_with_data(
+            BufferInfo {
+                size: std::mem::size_of::<Uniforms>(),
+                buffer_usage: BufferUsage::UNIFORM,
+                mapped_at_creation: false,
+            },
+            uniforms.as_bytes(),
+        );
+        if let Some((old_buffer, _)) = state.uniforms.replace((buffer, uniforms)) {
+            render_resource_context.remove_buffer(old_buffer);
+        }
+    }
+    let (uniform_buffer, _) = state.uniforms.unwrap();
+    render_resource_bindings.set(
+        "UiUniforms",
+        RenderResourceBinding::Buffer {
+            buffer: uniform_buffer,
+            range: 0..std::mem::size_of::<Uniforms>() as u64,
+            dynamic_index: None,
+        },
+    );
+    render_resource_bindings.update_bind_groups(pipeline_descriptor, &**render_resource_context);
+    let uniform_bind_group_descriptor = pipeline_descriptor.get_layout().unwrap().get_bind_group(1).unwrap();
+    let uniform_bind_group = render_resource_bindings
+        .get_descriptor_bind_group(uniform_bind_group_descriptor.id)
+        .unwrap();
+
     draw.clear();
-    draw.push(RenderCommand::SetPipeline { pipeline });
-    let mut bind_group_set = false;
+    // pipelines and bind groups are only switched when they change, also across ui entities
+    let mut current_pipeline = None;
+    let mut current_bind_group = None;
+    let mut current_clip = None;
+    let mut clip_bind_group_descriptor = None;
+    let (width, height) = (target_width as f32, target_height as f32);
+    let feather = render_settings.clip_feather.max(0.0);
+    let mut vertex_count = 0;
+    let mut texture_uploads = 0;
+    let mut redraws = 0;
+    let mut namespaces = HashSet::default();
 
-    for (mut ui_draw, stylesheet) in query.iter_mut() {
-        let textures = if let Some(&mut Stylesheet { ref mut textures, .. }) = stylesheets.get_mut(stylesheet) {
-            textures
-        } else {
-            continue;
+    // uis are drawn in order of their layer, uis without a layer are on layer 0
+    // every window and texture target has its own node, uis without a target are drawn into their window
+    let pass_target = &state.target;
+    let mut uis = query
+        .iter_mut()
+        .filter(|(.., target, window)| match (pass_target, target) {
+            (PassTarget::Texture(pass_target), Some(UiRenderTarget(texture))) => texture == &pass_target.texture,
+            (PassTarget::Window(id), None) => window.copied().unwrap_or_default().0 == *id,
+            _ => false,
+        })
+        .collect::<Vec<_>>();
+    uis.sort_by_key(|(_, _, _, layer, ..)| layer.copied().unwrap_or_default());
+
+    for (mut ui_draw, stylesheet, style_override, _, custom_pipeline, ..) in uis {
+        let namespace = match (style_override, stylesheet) {
+            (Some(style_override), _) => TextureNamespace::Override(style_override.namespace),
+            (None, Some(stylesheet)) if stylesheets.get(stylesheet).is_some() => {
+                TextureNamespace::Stylesheet(stylesheet.id)
+            }
+            _ => continue,
         };
+        namespaces.insert(namespace);
 
         let mut new_textures = HashMap::default();
         let mut updates = Vec::default();
@@ -286,57 +821,41 @@ fn render_ui(
                     new_textures.insert(id, (size, data, atlas));
                 }
                 Update::TextureSubresource { id, offset, size, data } => {
+                    // a later update of the exact same region replaces the earlier one
+                    updates.retain(|&(i, o, s, _)| (i, o, s) != (id, offset, size));
                     updates.push((id, offset, size, data));
                 }
             }
         }
 
-        for (id, (size, data, _atlas)) in new_textures {
-            let size = Extent3d {
-                width: size[0],
-                height: size[1],
-                depth: 1,
-            };
+        let mut uploads = TextureUploads::new(render_settings.coalesce_texture_uploads);
 
-            let padding = 256 - (size.width * 4) % 256;
-            let data = if padding > 0 {
-                data.chunks(size.width as usize * 4).fold(Vec::new(), |mut data, row| {
-                    data.extend_from_slice(row);
-                    data.extend(std::iter::repeat(0).take(padding as _));
-                    data
-                })
+        for (id, (size, data, atlas)) in new_textures {
+            // atlas textures pack glyphs and images that pixel_widgets adds over time with subresource updates.
+            // mipmaps of those would go stale and bleed neighbouring entries into each other, so they only get
+            // the base level. all textures are sampled with clamp to edge addressing, atlas or not.
+            let levels = if render_settings.mipmaps && !atlas {
+                mip_chain(size[0], size[1], data)
             } else {
-                data
+                vec![(size[0], size[1], data)]
             };
 
             let texture_id = render_resource_context.create_texture(TextureDescriptor {
-                size,
+                size: Extent3d {
+                    width: size[0],
+                    height: size[1],
+                    depth: 1,
+                },
+                mip_level_count: levels.len() as u32,
                 ..TextureDescriptor::default()
             });
 
-            if let Some(overwritten) = textures.insert(id, texture_id) {
-                render_resource_context.remove_texture(overwritten);
+            if let Some(overwritten) = state.textures.insert((namespace, id), texture_id) {
+                state.retired_textures.push((frame, overwritten));
             }
 
-            if !data.is_empty() {
-                let texture_data = render_resource_context.create_buffer_with_data(
-                    BufferInfo {
-                        size: data.len(),
-                        buffer_usage: BufferUsage::COPY_SRC,
-                        mapped_at_creation: false,
-                    },
-                    data.as_slice(),
-                );
-
-                state.command_queue.copy_buffer_to_texture(
-                    texture_data,
-                    0,
-                    size.width * 4 + padding,
-                    texture_id,
-                    [0; 3],
-                    0,
-                    size,
-                );
+            for (mip_level, (width, height, data)) in levels.into_iter().enumerate() {
+                uploads.push(data, texture_id, [0; 3], mip_level as u32, Extent3d { width, height, depth: 1 });
             }
         }
 
@@ -347,40 +866,68 @@ fn render_ui(
                 depth: 1,
             };
 
-            let padding = 256 - (size.width * 4) % 256;
-            let data = if padding > 0 {
-                data.chunks(size.width as usize * 4).fold(Vec::new(), |mut data, row| {
-                    data.extend_from_slice(row);
-                    data.extend(std::iter::repeat(0).take(padding as _));
-                    data
-                })
+            // the texture may belong to a stylesheet that was swapped out since
+            let texture_id = if let Some(&texture_id) = state.textures.get(&(namespace, id)) {
+                texture_id
             } else {
-                data
+                continue;
             };
+            uploads.push(data, texture_id, [offset[0], offset[1], 0], 0, size);
+        }
+
+        if ui_draw.redrawn {
+            ui_draw.redrawn = false;
+            redraws += 1;
+        }
 
-            let texture_data = render_resource_context.create_buffer_with_data(
+        texture_uploads += uploads.copies.len();
+        if !uploads.data.is_empty() {
+            let staging_buffer = render_resource_context.create_buffer_with_data(
                 BufferInfo {
-                    size: data.len(),
+                    size: uploads.data.len(),
                     buffer_usage: BufferUsage::COPY_SRC,
                     mapped_at_creation: false,
                 },
-                data.as_slice(),
+                uploads.data.as_slice(),
             );
 
-            let texture_id = textures.get(&id).cloned().unwrap();
-
-            state.command_queue.copy_buffer_to_texture(
-                texture_data,
-                0,
-                size.width * 4 + padding,
-                texture_id,
-                [offset[0], offset[1], 0],
-                0,
-                size,
-            );
+            for copy in uploads.copies {
+                state.command_queue.copy_buffer_to_texture(
+                    staging_buffer,
+                    copy.offset,
+                    copy.bytes_per_row,
+                    copy.texture,
+                    copy.origin,
+                    copy.mip_level,
+                    copy.size,
+                );
+            }
+            state.staging_buffers.push(staging_buffer);
         }
 
-        if ui_draw.vertices.is_some() {
+        if ui_draw.vertices.is_some() && target_width > 0 && target_height > 0 {
+            let ui_pipeline = custom_pipeline
+                .and_then(|UiCustomPipeline(handle)| custom_pipelines.get(&handle.id))
+                .unwrap_or(&pipeline);
+            if current_pipeline != Some(ui_pipeline) {
+                // bind groups have to be set again after switching pipelines
+                draw.push(RenderCommand::SetPipeline {
+                    pipeline: ui_pipeline.clone(),
+                });
+                draw.push(RenderCommand::SetBindGroup {
+                    index: uniform_bind_group_descriptor.index,
+                    bind_group: uniform_bind_group.id,
+                    dynamic_uniform_indices: None,
+                });
+                current_pipeline = Some(ui_pipeline);
+                current_bind_group = None;
+                // custom pipelines may not have the clip uniforms of the UI shaders
+                clip_bind_group_descriptor = pipelines
+                    .get(ui_pipeline)
+                    .and_then(|pipeline| pipeline.get_layout())
+                    .and_then(|layout| layout.get_bind_group(2));
+                current_clip = None;
+            }
             draw.push(RenderCommand::SetVertexBuffer {
                 slot: 0,
                 buffer: ui_draw.vertices.unwrap(),
@@ -389,75 +936,249 @@ fn render_ui(
             draw.push(RenderCommand::SetScissorRect {
                 x: 0,
                 y: 0,
-                w: window.physical_width(),
-                h: window.physical_height(),
+                w: target_width,
+                h: target_height,
             });
+            if let Some(descriptor) = clip_bind_group_descriptor {
+                let clip = ClipUniforms::new([0.0, 0.0, width, height], feather, (width, height));
+                let context = &**render_resource_context;
+                push_clip(&mut draw, &mut state, clip, descriptor, &mut current_clip, context);
+            }
 
             for command in ui_draw.commands.iter() {
                 match command {
                     pixel_widgets::draw::Command::Nop => (),
                     pixel_widgets::draw::Command::Clip { scissor } => {
-                        let scale = window.scale_factor() as f32;
+                        // clips are absolute, pixel_widgets already intersects them with the clips of parents.
+                        // they can still reach outside of the target though, which isn't a valid scissor rect.
+                        let scale = ui_draw.scale_factor;
+                        let left = (scissor.left * scale).max(0.0).min(width);
+                        let top = (scissor.top * scale).max(0.0).min(height);
+                        let right = (scissor.right * scale).max(left).min(width);
+                        let bottom = (scissor.bottom * scale).max(top).min(height);
                         draw.push(RenderCommand::SetScissorRect {
-                            x: (scissor.left * scale) as u32,
-                            y: (scissor.top * scale) as u32,
-                            w: (scissor.width() * scale) as u32,
-                            h: (scissor.height() * scale) as u32,
-                        })
+                            x: left as u32,
+                            y: top as u32,
+                            w: (right - left) as u32,
+                            h: (bottom - top) as u32,
+                        });
+                        if let Some(descriptor) = clip_bind_group_descriptor.filter(|_| feather > 0.0) {
+                            let clip = ClipUniforms::new([left, top, right, bottom], feather, (width, height));
+                            let context = &**render_resource_context;
+                            push_clip(&mut draw, &mut state, clip, descriptor, &mut current_clip, context);
+                        }
                     }
                     &pixel_widgets::draw::Command::Colored { offset, count } => {
-                        if !bind_group_set {
-                            // just create a bind group for the first texture
-                            let first_texture = textures.iter().next().unwrap();
-                            render_resource_bindings.set("t_Color", RenderResourceBinding::Texture(*first_texture.1));
-                            render_resource_bindings.set("s_Color", RenderResourceBinding::Sampler(sampler_id));
-                            render_resource_bindings
-                                .update_bind_groups(pipeline_descriptor, &**render_resource_context);
-                            let bind_group = render_resource_bindings
-                                .get_descriptor_bind_group(bind_group_descriptor.id)
-                                .unwrap();
+                        // the UI shaders ignore the texture of colored draws, but custom pipelines may still sample it
+                        let white_texture = state.white_texture(&**render_resource_context);
+                        let bind_group = state.texture_bind_group(
+                            white_texture,
+                            sampler_id,
+                            bind_group_descriptor.id,
+                            &**render_resource_context,
+                        );
+                        if current_bind_group != Some(bind_group) {
                             draw.push(RenderCommand::SetBindGroup {
                                 index: bind_group_descriptor.index,
-                                bind_group: bind_group.id,
-                                dynamic_uniform_indices: None
+                                bind_group,
+                                dynamic_uniform_indices: None,
                             });
-
-                            bind_group_set = true;
+                            current_bind_group = Some(bind_group);
                         }
-                        draw.push(RenderCommand::Draw {
-                            vertices: (offset as u32)..(offset + count) as u32,
-                            instances: 0..1,
-                        });
+                        vertex_count += count;
+                        push_draw(&mut draw, (offset as u32)..(offset + count) as u32);
                     }
                     &pixel_widgets::draw::Command::Textured { texture, offset, count } => {
-                        let texture = textures.get(&texture).cloned().unwrap();
-                        render_resource_bindings.set("t_Color", RenderResourceBinding::Texture(texture));
-                        render_resource_bindings.set("s_Color", RenderResourceBinding::Sampler(sampler_id));
-                        render_resource_bindings.update_bind_groups(pipeline_descriptor, &**render_resource_context);
-                        let bind_group = render_resource_bindings
-                            .get_descriptor_bind_group(bind_group_descriptor.id)
-                            .unwrap();
-                        draw.push(RenderCommand::SetBindGroup {
-                            index: bind_group_descriptor.index,
-                            bind_group: bind_group.id,
-                            dynamic_uniform_indices: None
-                        });
-
-                        bind_group_set = true;
+                        // stale commands may refer to textures of a stylesheet that was swapped out
+                        let texture = if let Some(&texture) = state.textures.get(&(namespace, texture)) {
+                            texture
+                        } else {
+                            continue;
+                        };
+                        let bind_group = state.texture_bind_group(
+                            texture,
+                            sampler_id,
+                            bind_group_descriptor.id,
+                            &**render_resource_context,
+                        );
+                        if current_bind_group != Some(bind_group) {
+                            draw.push(RenderCommand::SetBindGroup {
+                                index: bind_group_descriptor.index,
+                                bind_group,
+                                dynamic_uniform_indices: None
+                            });
+                            current_bind_group = Some(bind_group);
+                        }
 
-                        draw.push(RenderCommand::Draw {
-                            vertices: (offset as u32)..(offset + count) as u32,
-                            instances: 0..1,
-                        });
+                        vertex_count += count;
+                        push_draw(&mut draw, (offset as u32)..(offset + count) as u32);
                     }
                 }
             }
         }
     }
 
+    // free the textures of stylesheets that are no longer used by any ui, like those of despawned uis.
+    // uis that start using a stylesheet again receive its textures once more when it's set.
+    let State {
+        textures,
+        retired_textures,
+        ..
+    } = &mut *state;
+    textures.retain(|(namespace, _), texture| {
+        namespaces.contains(namespace) || {
+            retired_textures.push((frame, *texture));
+            false
+        }
+    });
+
+    // clip rects that weren't drawn with this frame, their bind groups are freed by bevy
+    state.clips.retain(|_, clip| {
+        clip.used == frame || {
+            render_resource_context.remove_buffer(clip.buffer);
+            false
+        }
+    });
+
+    // the diagnostics are about the uis in the primary window
+    let primary = matches!(state.target, PassTarget::Window(id) if id.is_primary());
+    if let Some(mut diagnostics) = diagnostics.filter(|_| primary) {
+        let draw_calls = draw
+            .iter()
+            .filter(|command| matches!(command, RenderCommand::Draw { .. }))
+            .count();
+        diagnostics.add_measurement(UiDiagnostics::DRAW_CALLS, draw_calls as f64);
+        diagnostics.add_measurement(UiDiagnostics::VERTICES, vertex_count as f64);
+        diagnostics.add_measurement(UiDiagnostics::TEXTURE_UPLOADS, texture_uploads as f64);
+        diagnostics.add_measurement(UiDiagnostics::REDRAWS, redraws as f64);
+    }
+
     *state.command_buffer.lock().unwrap() = draw;
 }
 
+/// Pushes a draw of `vertices`, or extends the previous draw if it ends where `vertices` starts.
+/// Bind groups are only set when they change, so consecutive draws always use the same one.
+fn push_draw(draw: &mut Vec<RenderCommand>, vertices: Range<u32>) {
+    if let Some(RenderCommand::Draw { vertices: previous, .. }) = draw.last_mut() {
+        if previous.end == vertices.start {
+            previous.end = vertices.end;
+            return;
+        }
+    }
+    draw.push(RenderCommand::Draw {
+        vertices,
+        instances: 0..1,
+    });
+}
+
+/// Sets the bind group of the `UiClip` uniforms of `clip`, unless it's set already.
+fn push_clip(
+    draw: &mut Vec<RenderCommand>,
+    state: &mut State,
+    clip: ClipUniforms,
+    descriptor: &BindGroupDescriptor,
+    current_clip: &mut Option<BindGroupId>,
+    render_resource_context: &dyn RenderResourceContext,
+) {
+    let bind_group = state.clip_bind_group(clip, descriptor.id, render_resource_context);
+    if *current_clip != Some(bind_group) {
+        draw.push(RenderCommand::SetBindGroup {
+            index: descriptor.index,
+            bind_group,
+            dynamic_uniform_indices: None,
+        });
+        *current_clip = Some(bind_group);
+    }
+}
+
+impl TextureUploads {
+    fn new(coalesce: bool) -> Self {
+        Self {
+            coalesce,
+            ..Self::default()
+        }
+    }
+
+    fn push(&mut self, data: Vec<u8>, texture: TextureId, origin: [u32; 3], mip_level: u32, size: Extent3d) {
+        if data.is_empty() {
+            return;
+        }
+
+        // padded rows are a multiple of 256 bytes, so every copy starts at an aligned offset
+        let (data, bytes_per_row) = pad_rows(data, size.width);
+        // the data of the last copy ends where this data starts, so a region right below it just adds rows
+        if let Some(previous) = self.copies.last_mut() {
+            if self.coalesce
+                && previous.texture == texture
+                && previous.mip_level == mip_level
+                && previous.size.width == size.width
+                && previous.origin[0] == origin[0]
+                && previous.origin[1] + previous.size.height == origin[1]
+            {
+                previous.size.height += size.height;
+                self.data.extend(data);
+                return;
+            }
+        }
+        self.copies.push(TextureCopy {
+            offset: self.data.len() as u64,
+            bytes_per_row,
+            texture,
+            origin,
+            mip_level,
+            size,
+        });
+        self.data.extend(data);
+    }
+}
+
+/// Pads the rows of rgba `data` to the row alignment required for buffer to texture copies.
+/// Returns the padded data and the number of bytes per row.
+fn pad_rows(data: Vec<u8>, width: u32) -> (Vec<u8>, u32) {
+    let padding = (256 - (width * 4) % 256) % 256;
+    let data = if padding > 0 {
+        data.chunks(width as usize * 4).fold(Vec::new(), |mut data, row| {
+            data.extend_from_slice(row);
+            data.extend(std::iter::repeat(0).take(padding as _));
+            data
+        })
+    } else {
+        data
+    };
+    (data, width * 4 + padding)
+}
+
+/// Builds the full mip chain of an rgba image by repeatedly halving it with a box filter.
+fn mip_chain(width: u32, height: u32, data: Vec<u8>) -> Vec<(u32, u32, Vec<u8>)> {
+    let mut levels = vec![(width, height, data)];
+    loop {
+        let &(w, h, ref src) = levels.last().unwrap();
+        if (w <= 1 && h <= 1) || src.is_empty() {
+            break;
+        }
+
+        let (next_w, next_h) = ((w / 2).max(1), (h / 2).max(1));
+        let mut dst = Vec::with_capacity((next_w * next_h * 4) as usize);
+        for y in 0..next_h {
+            for x in 0..next_w {
+                for c in 0..4 {
+                    let sum: u32 = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                        .iter()
+                        .map(|&(dx, dy)| {
+                            let sx = (x * 2 + dx).min(w - 1);
+                            let sy = (y * 2 + dy).min(h - 1);
+                            src[((sy * w + sx) * 4 + c) as usize] as u32
+                        })
+                        .sum();
+                    dst.push((sum / 4) as u8);
+                }
+            }
+        }
+        levels.push((next_w, next_h, dst));
+    }
+    levels
+}
+
 /// Tracks the current pipeline state to ensure draw calls are valid.
 #[derive(Debug, Default)]
 struct DrawState {
@@ -492,3 +1213,1054 @@ impl DrawState {
         self.vertex_buffers.resize(layout.vertex_buffer_descriptors.len(), None);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(width: u32, height: u32) -> (Vec<u8>, Extent3d) {
+        let size = Extent3d {
+            width,
+            height,
+            depth: 1,
+        };
+        (vec![255; (width * height * 4) as usize], size)
+    }
+
+    #[test]
+    fn adjacent_uploads_are_coalesced() {
+        let texture = TextureId::new();
+        let mut uploads = TextureUploads::new(true);
+        for y in 0..4 {
+            let (data, size) = rows(10, 8);
+            uploads.push(data, texture, [16, y * 8, 0], 0, size);
+        }
+        // a different column can't be merged
+        let (data, size) = rows(10, 8);
+        uploads.push(data, texture, [32, 0, 0], 0, size);
+
+        assert_eq!(uploads.copies.len(), 2);
+        assert_eq!(uploads.copies[0].size.height, 32);
+        assert_eq!(uploads.copies[1].offset, 256 * 32);
+        assert_eq!(uploads.data.len(), 256 * 40);
+    }
+
+    #[test]
+    fn uploads_are_not_coalesced_when_disabled() {
+        let texture = TextureId::new();
+        let mut uploads = TextureUploads::new(false);
+        for y in 0..4 {
+            let (data, size) = rows(10, 8);
+            uploads.push(data, texture, [16, y * 8, 0], 0, size);
+        }
+        assert_eq!(uploads.copies.len(), 4);
+    }
+
+    #[test]
+    fn aligned_rows_are_not_padded() {
+        let data = vec![255; 64 * 4 * 2];
+        let (padded, bytes_per_row) = pad_rows(data.clone(), 64);
+        assert_eq!(bytes_per_row, 256);
+        assert_eq!(padded, data);
+    }
+
+    #[test]
+    fn rows_are_padded_to_256_bytes() {
+        let (padded, bytes_per_row) = pad_rows(vec![255; 10 * 4 * 2], 10);
+        assert_eq!(bytes_per_row, 256);
+        assert_eq!(padded.len(), 256 * 2);
+        assert_eq!(&padded[..40], &[255; 40][..]);
+        assert!(padded[40..256].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn mip_chain_halves_down_to_one_pixel() {
+        let (data, _) = rows(8, 2);
+        let levels = mip_chain(8, 2, data);
+        let sizes = levels.iter().map(|&(w, h, _)| (w, h)).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![(8, 2), (4, 1), (2, 1), (1, 1)]);
+        for (w, h, data) in levels {
+            assert_eq!(data.len(), (w * h * 4) as usize);
+            assert!(data.iter().all(|&byte| byte == 255));
+        }
+    }
+
+    #[test]
+    fn clip_edges_on_the_border_of_the_target_are_moved_outwards() {
+        let clip = ClipUniforms::new([0.0, 10.0, 800.0, 50.0], 2.0, (800.0, 600.0));
+        assert_eq!(clip.rect, [-2.0, 10.0, 802.0, 50.0]);
+        let clip = ClipUniforms::new([10.0, 0.0, 110.0, 600.0], 2.0, (800.0, 600.0));
+        assert_eq!(clip.rect, [10.0, -2.0, 110.0, 602.0]);
+    }
+}
+
+#[cfg(test)]
+mod render_tests {
+    use bevy::render::render_graph::{base, NodeLabel, RenderGraph, WindowSwapChainNode};
+    use bevy::window::WindowId;
+
+    use super::*;
+    use crate::pipeline::{specialized_ui_pipeline, UiBlend, UI_FRAGMENT_SHADER_HANDLE};
+    use crate::plugin::{
+        add_ui_window_pass, texture_target_node, window_node, UiDepth, UiPlugin, UiWindowNodes, PIXEL_WIDGETS,
+    };
+    use crate::test_util::*;
+    use crate::testing::ResourceCall;
+
+    /// The vertex buffers that the node `label` draws.
+    fn drawn_vertex_buffers(app: &App, label: impl Into<NodeLabel>) -> Vec<BufferId> {
+        let render_graph = app.world.get_resource::<RenderGraph>().unwrap();
+        let node = render_graph.get_node::<UiNode>(label).unwrap();
+        let command_buffer = node.command_buffer.lock().unwrap();
+        command_buffer
+            .iter()
+            .filter_map(|command| match command {
+                RenderCommand::SetVertexBuffer { buffer, .. } => Some(*buffer),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The contents of the buffer that is bound as `UiUniforms`, if it was created in `calls`.
+    fn bound_uniforms(app: &App, calls: &[ResourceCall]) -> Vec<u8> {
+        let bindings = app.world.get_resource::<RenderResourceBindings>().unwrap();
+        let bound = match bindings.get("UiUniforms") {
+            Some(RenderResourceBinding::Buffer { buffer, .. }) => *buffer,
+            binding => panic!("UiUniforms is bound to {:?}", binding),
+        };
+        calls
+            .iter()
+            .find_map(|call| match call {
+                ResourceCall::CreateBuffer {
+                    buffer,
+                    data: Some(data),
+                    ..
+                } if *buffer == bound => Some(data.clone()),
+                _ => None,
+            })
+            .expect("the bound uniform buffer wasn't created")
+    }
+
+    /// The bind groups with a texture that were created in `calls`.
+    fn created_texture_bind_groups(calls: &[ResourceCall]) -> Vec<BindGroup> {
+        calls
+            .iter()
+            .filter_map(|call| match call {
+                ResourceCall::CreateBindGroup(bind_group)
+                    if bind_group
+                        .indexed_bindings
+                        .iter()
+                        .any(|binding| matches!(binding.entry, RenderResourceBinding::Texture(_))) =>
+                {
+                    Some(bind_group.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The textures that the node `label` binds in order, resolved through the bind groups created in `calls`.
+    fn bound_textures(app: &App, label: impl Into<NodeLabel>, calls: &[ResourceCall]) -> Vec<TextureId> {
+        let bind_groups = created_texture_bind_groups(calls);
+        let render_graph = app.world.get_resource::<RenderGraph>().unwrap();
+        let node = render_graph.get_node::<UiNode>(label).unwrap();
+        let command_buffer = node.command_buffer.lock().unwrap();
+        command_buffer
+            .iter()
+            .filter_map(|command| match command {
+                RenderCommand::SetBindGroup { bind_group, .. } => {
+                    bind_groups.iter().find(|created| created.id == *bind_group)
+                }
+                _ => None,
+            })
+            .filter_map(|bind_group| {
+                bind_group
+                    .indexed_bindings
+                    .iter()
+                    .find_map(|binding| match &binding.entry {
+                        RenderResourceBinding::Texture(texture) => Some(*texture),
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+
+    fn default_uniforms() -> Uniforms {
+        Uniforms {
+            color_filter: UiColorFilter::IDENTITY.0.to_cols_array(),
+            gamma: 1.0,
+            flip_y: 0.0,
+            _padding: [0.0; 2],
+        }
+    }
+
+    #[test]
+    fn color_filter_reaches_uniforms() {
+        let mut app = render_app(UiPlugin::default());
+        spawn_counter(&mut app.world);
+        app.world.insert_resource(UiColorFilter::sepia());
+        app.update();
+
+        let expected = Uniforms {
+            color_filter: UiColorFilter::sepia().0.to_cols_array(),
+            ..default_uniforms()
+        };
+        assert_eq!(bound_uniforms(&app, &take_calls(&app)), expected.as_bytes());
+
+        // a new filter replaces the uniform buffer
+        app.world.insert_resource(UiColorFilter::grayscale());
+        app.update();
+        let expected = Uniforms {
+            color_filter: UiColorFilter::grayscale().0.to_cols_array(),
+            ..default_uniforms()
+        };
+        assert_eq!(bound_uniforms(&app, &take_calls(&app)), expected.as_bytes());
+    }
+
+    #[test]
+    fn gamma_reaches_uniforms() {
+        let mut app = render_app(UiPlugin::default());
+        spawn_counter(&mut app.world);
+        app.world.insert_resource(UiGamma(2.2));
+        app.update();
+
+        let expected = Uniforms {
+            gamma: 2.2,
+            ..default_uniforms()
+        };
+        assert_eq!(bound_uniforms(&app, &take_calls(&app)), expected.as_bytes());
+    }
+
+    #[test]
+    fn non_positive_gamma_is_clamped() {
+        let mut app = render_app(UiPlugin::default());
+        spawn_counter(&mut app.world);
+        app.world.insert_resource(UiGamma(0.0));
+        app.update();
+
+        let expected = Uniforms {
+            gamma: f32::EPSILON,
+            ..default_uniforms()
+        };
+        assert_eq!(bound_uniforms(&app, &take_calls(&app)), expected.as_bytes());
+    }
+
+    #[test]
+    fn flip_y_reaches_uniforms() {
+        let mut app = render_app(UiPlugin::default());
+        spawn_counter(&mut app.world);
+        app.world.get_resource_mut::<UiRenderSettings>().unwrap().flip_y = true;
+        app.update();
+
+        let expected = Uniforms {
+            flip_y: 1.0,
+            ..default_uniforms()
+        };
+        assert_eq!(bound_uniforms(&app, &take_calls(&app)), expected.as_bytes());
+    }
+
+    #[test]
+    fn diagnostics_are_registered_and_updated() {
+        let mut app = render_app(UiPlugin::default());
+        spawn_counter(&mut app.world);
+        app.update();
+
+        let diagnostics = app.world.get_resource::<Diagnostics>().unwrap();
+        let value = |id| diagnostics.get(id).and_then(|diagnostic| diagnostic.value());
+        assert!(value(UiDiagnostics::DRAW_CALLS).unwrap() >= 1.0);
+        assert!(value(UiDiagnostics::VERTICES).unwrap() > 0.0);
+        assert_eq!(value(UiDiagnostics::REDRAWS), Some(1.0));
+        assert!(value(UiDiagnostics::TEXTURE_UPLOADS).is_some());
+
+        // nothing changed, so nothing is redrawn
+        app.update();
+        let diagnostics = app.world.get_resource::<Diagnostics>().unwrap();
+        let redraws = diagnostics.get(UiDiagnostics::REDRAWS).unwrap();
+        assert_eq!(redraws.value(), Some(0.0));
+    }
+
+    #[test]
+    fn swapping_a_shader_recompiles_the_pipeline() {
+        let mut app = render_app(UiPlugin::default());
+        spawn_counter(&mut app.world);
+        app.update();
+
+        let specialized_pipeline = |app: &App| {
+            let pipeline_compiler = app.world.get_resource::<PipelineCompiler>().unwrap();
+            let pipelines = app.world.get_resource::<Assets<PipelineDescriptor>>().unwrap();
+            let msaa = app.world.get_resource::<Msaa>().unwrap();
+            specialized_ui_pipeline(pipeline_compiler, pipelines, msaa).unwrap()
+        };
+        let before = specialized_pipeline(&app);
+
+        let source = format!("{}\n// swapped\n", include_str!("ui.frag"));
+        let shader = Shader::from_glsl(bevy::render::shader::ShaderStage::Fragment, &source);
+        app.world
+            .get_resource_mut::<Assets<Shader>>()
+            .unwrap()
+            .set_untracked(UI_FRAGMENT_SHADER_HANDLE, shader);
+        app.update();
+
+        assert_ne!(specialized_pipeline(&app), before);
+    }
+
+    #[test]
+    fn wireframe_draws_lines() {
+        let mut app = render_app(UiPlugin::default());
+        spawn_counter(&mut app.world);
+        app.world.insert_resource(UiWireframe(true));
+        app.update();
+
+        let render_graph = app.world.get_resource::<RenderGraph>().unwrap();
+        let node = render_graph.get_node::<UiNode>(PIXEL_WIDGETS).unwrap();
+        let pipelines = app.world.get_resource::<Assets<PipelineDescriptor>>().unwrap();
+        let command_buffer = node.command_buffer.lock().unwrap();
+        let mut set_pipelines = command_buffer
+            .iter()
+            .filter_map(|command| match command {
+                RenderCommand::SetPipeline { pipeline } => pipelines.get(pipeline),
+                _ => None,
+            })
+            .peekable();
+        assert!(set_pipelines.peek().is_some());
+        assert!(set_pipelines.all(|pipeline| pipeline.primitive.polygon_mode == PolygonMode::Line));
+    }
+
+    #[test]
+    fn layers_are_drawn_in_order_in_one_command_stream() {
+        let mut app = render_app(UiPlugin::default());
+        let top = spawn_counter(&mut app.world);
+        let bottom = spawn_counter(&mut app.world);
+        let middle = spawn_counter(&mut app.world);
+        app.world.entity_mut(top).insert(UiLayer(2));
+        app.world.entity_mut(bottom).insert(UiLayer(-1));
+        app.update();
+
+        let vertices = |entity| app.world.get::<UiDraw>(entity).unwrap().vertices.unwrap();
+        assert_eq!(
+            drawn_vertex_buffers(&app, PIXEL_WIDGETS),
+            vec![vertices(bottom), vertices(middle), vertices(top)]
+        );
+
+        // all uis share the pipeline, so it's only set once
+        let render_graph = app.world.get_resource::<RenderGraph>().unwrap();
+        let node = render_graph.get_node::<UiNode>(PIXEL_WIDGETS).unwrap();
+        let command_buffer = node.command_buffer.lock().unwrap();
+        let set_pipelines = command_buffer
+            .iter()
+            .filter(|command| matches!(command, RenderCommand::SetPipeline { .. }))
+            .count();
+        assert_eq!(set_pipelines, 1);
+    }
+
+    #[test]
+    fn uis_are_drawn_with_their_own_pipelines() {
+        let mut app = render_app(UiPlugin::default());
+        let ui_pipeline = UI_PIPELINE_HANDLE.clone().typed::<PipelineDescriptor>();
+        let custom_pipeline = {
+            let mut pipelines = app.world.get_resource_mut::<Assets<PipelineDescriptor>>().unwrap();
+            let descriptor = pipelines.get(&ui_pipeline).unwrap().clone();
+            pipelines.add(descriptor)
+        };
+        let default_ui = spawn_counter(&mut app.world);
+        let custom_ui = spawn_counter(&mut app.world);
+        app.world
+            .entity_mut(custom_ui)
+            .insert_bundle((UiCustomPipeline(custom_pipeline.clone()), UiLayer(1)));
+        app.update();
+
+        let pipeline_compiler = app.world.get_resource::<PipelineCompiler>().unwrap();
+        let pipelines = app.world.get_resource::<Assets<PipelineDescriptor>>().unwrap();
+        let msaa = app.world.get_resource::<Msaa>().unwrap();
+        let specialization = PipelineSpecialization {
+            sample_count: msaa.samples,
+            ..ui_pipeline_specialization(pipelines).unwrap()
+        };
+        let specialized = |handle| {
+            pipeline_compiler
+                .get_specialized_pipeline(handle, &specialization)
+                .unwrap()
+        };
+        let vertices = |entity| app.world.get::<UiDraw>(entity).unwrap().vertices.unwrap();
+
+        // the pipeline that is set when each vertex buffer is drawn
+        let render_graph = app.world.get_resource::<RenderGraph>().unwrap();
+        let node = render_graph.get_node::<UiNode>(PIXEL_WIDGETS).unwrap();
+        let mut current_pipeline = None;
+        let mut drawn = Vec::new();
+        for command in node.command_buffer.lock().unwrap().iter() {
+            match command {
+                RenderCommand::SetPipeline { pipeline } => current_pipeline = Some(pipeline.clone()),
+                RenderCommand::SetVertexBuffer { buffer, .. } => {
+                    drawn.push((*buffer, current_pipeline.clone().unwrap()))
+                }
+                _ => (),
+            }
+        }
+        assert_eq!(
+            drawn,
+            vec![
+                (vertices(default_ui), specialized(&ui_pipeline)),
+                (vertices(custom_ui), specialized(&custom_pipeline)),
+            ]
+        );
+        assert_ne!(specialized(&ui_pipeline), specialized(&custom_pipeline));
+    }
+
+    #[test]
+    fn scale_factor_override_scales_clips() {
+        let mut app = render_app(UiPlugin::default());
+        let entity = spawn_counter(&mut app.world);
+        app.world.get_mut::<Ui<Counter>>(entity).unwrap().set_scale_factor(2.0);
+        app.update();
+
+        app.world.get_mut::<UiDraw>(entity).unwrap().commands = vec![pixel_widgets::draw::Command::Clip {
+            scissor: Rectangle {
+                left: 10.0,
+                top: 10.0,
+                right: 110.0,
+                bottom: 50.0,
+            },
+        }];
+        app.update();
+
+        let render_graph = app.world.get_resource::<RenderGraph>().unwrap();
+        let node = render_graph.get_node::<UiNode>(PIXEL_WIDGETS).unwrap();
+        let command_buffer = node.command_buffer.lock().unwrap();
+        assert!(command_buffer.contains(&RenderCommand::SetScissorRect {
+            x: 20,
+            y: 20,
+            w: 200,
+            h: 80,
+        }));
+    }
+
+    #[test]
+    fn feathered_clips_reach_the_shader() {
+        let mut app = render_app(UiPlugin::default());
+        let entity = spawn_counter(&mut app.world);
+        app.world.get_resource_mut::<UiRenderSettings>().unwrap().clip_feather = 2.0;
+        app.update();
+
+        app.world.get_mut::<UiDraw>(entity).unwrap().commands = vec![pixel_widgets::draw::Command::Clip {
+            scissor: Rectangle {
+                left: 10.0,
+                top: 10.0,
+                right: 110.0,
+                bottom: 50.0,
+            },
+        }];
+        app.update();
+
+        let expected = ClipUniforms {
+            rect: [10.0, 10.0, 110.0, 50.0],
+            feather: 2.0,
+            _padding: [0.0; 3],
+        };
+        let calls = take_calls(&app);
+        let buffer = calls
+            .iter()
+            .find_map(|call| match call {
+                ResourceCall::CreateBuffer {
+                    buffer,
+                    data: Some(data),
+                    ..
+                } if data.as_slice() == expected.as_bytes() => Some(*buffer),
+                _ => None,
+            })
+            .expect("the clip uniforms weren't created");
+        let binding = RenderResourceBinding::Buffer {
+            buffer,
+            range: 0..std::mem::size_of::<ClipUniforms>() as u64,
+            dynamic_index: None,
+        };
+        let bind_group = calls
+            .iter()
+            .find_map(|call| match call {
+                ResourceCall::CreateBindGroup(bind_group)
+                    if bind_group
+                        .indexed_bindings
+                        .iter()
+                        .any(|indexed| indexed.entry == binding) =>
+                {
+                    Some(bind_group.id)
+                }
+                _ => None,
+            })
+            .expect("the clip bind group wasn't created");
+
+        let render_graph = app.world.get_resource::<RenderGraph>().unwrap();
+        let node = render_graph.get_node::<UiNode>(PIXEL_WIDGETS).unwrap();
+        let command_buffer = node.command_buffer.lock().unwrap();
+        assert!(command_buffer.contains(&RenderCommand::SetBindGroup {
+            index: 2,
+            bind_group,
+            dynamic_uniform_indices: None,
+        }));
+    }
+
+    #[test]
+    fn clips_are_hard_without_feathering() {
+        let mut app = render_app(UiPlugin::default());
+        let entity = spawn_counter(&mut app.world);
+        app.update();
+
+        app.world.get_mut::<UiDraw>(entity).unwrap().commands = vec![pixel_widgets::draw::Command::Clip {
+            scissor: Rectangle {
+                left: 10.0,
+                top: 10.0,
+                right: 110.0,
+                bottom: 50.0,
+            },
+        }];
+        app.update();
+
+        // the clip uniforms of the whole target are set once, the clip command only sets the scissor rect
+        let render_graph = app.world.get_resource::<RenderGraph>().unwrap();
+        let node = render_graph.get_node::<UiNode>(PIXEL_WIDGETS).unwrap();
+        let command_buffer = node.command_buffer.lock().unwrap();
+        let clips = command_buffer
+            .iter()
+            .filter(|command| matches!(command, RenderCommand::SetBindGroup { index: 2, .. }))
+            .count();
+        assert_eq!(clips, 1);
+    }
+
+    #[test]
+    fn replaced_textures_are_freed_after_the_release_delay() {
+        let mut app = render_app(UiPlugin::default());
+        let entity = spawn_counter(&mut app.world);
+        app.update();
+
+        let upload = |app: &mut App| {
+            let mut draw = app.world.get_mut::<UiDraw>(entity).unwrap();
+            draw.updates.push(Update::Texture {
+                id: 100,
+                size: [4, 4],
+                data: vec![255; 64],
+                atlas: false,
+            });
+        };
+        upload(&mut app);
+        app.update();
+        let texture = take_calls(&app)
+            .into_iter()
+            .find_map(|call| match call {
+                ResourceCall::CreateTexture { texture, descriptor } if descriptor.size.width == 4 => Some(texture),
+                _ => None,
+            })
+            .unwrap();
+
+        // uploading the same texture id again replaces the texture
+        upload(&mut app);
+        app.update();
+        for _ in 0..UiRenderSettings::default().texture_release_delay {
+            app.update();
+            assert!(!take_calls(&app).contains(&ResourceCall::RemoveTexture(texture)));
+        }
+        app.update();
+        assert!(take_calls(&app).contains(&ResourceCall::RemoveTexture(texture)));
+    }
+
+    #[test]
+    fn stylesheet_images_get_mipmaps_but_atlases_dont() {
+        let mut app = render_app(UiPlugin::default());
+        app.world.get_resource_mut::<UiRenderSettings>().unwrap().mipmaps = true;
+        let entity = spawn_counter(&mut app.world);
+        app.update();
+        take_calls(&app);
+
+        let mut draw = app.world.get_mut::<UiDraw>(entity).unwrap();
+        for &(id, size, atlas) in [(100, 4, false), (101, 8, true)].iter() {
+            draw.updates.push(Update::Texture {
+                id,
+                size: [size, size],
+                data: vec![255; (size * size * 4) as usize],
+                atlas,
+            });
+        }
+        app.update();
+
+        let calls = take_calls(&app);
+        let mip_levels = |width| {
+            calls
+                .iter()
+                .find_map(|call| match call {
+                    ResourceCall::CreateTexture { descriptor, .. } if descriptor.size.width == width => {
+                        Some(descriptor.mip_level_count)
+                    }
+                    _ => None,
+                })
+                .unwrap()
+        };
+        assert_eq!(mip_levels(4), 3);
+        assert_eq!(mip_levels(8), 1);
+    }
+
+    #[test]
+    fn color_only_ui_binds_the_white_texture() {
+        fn drop_texture_updates(mut query: Query<&mut UiDraw>) {
+            for mut draw in query.iter_mut() {
+                draw.updates.clear();
+            }
+        }
+
+        let mut app = render_app(UiPlugin::default());
+        // without any textures of its stylesheet, the ui can only draw with the white texture
+        app.schedule
+            .add_system_to_stage(CoreStage::PostUpdate, drop_texture_updates.system());
+        spawn_counter(&mut app.world);
+        app.update();
+
+        let calls = take_calls(&app);
+        let white_texture = calls
+            .iter()
+            .find_map(|call| match call {
+                ResourceCall::CreateTexture { texture, descriptor }
+                    if (descriptor.size.width, descriptor.size.height) == (1, 1) =>
+                {
+                    Some(*texture)
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(bound_textures(&app, PIXEL_WIDGETS, &calls), vec![white_texture]);
+    }
+
+    #[test]
+    fn colored_draws_after_textured_draws_bind_the_white_texture() {
+        let mut app = render_app(UiPlugin::default());
+        let entity = spawn_counter(&mut app.world);
+        app.update();
+        // the white texture and its bind group may already be created in the first frame
+        let mut calls = take_calls(&app);
+
+        let mut draw = app.world.get_mut::<UiDraw>(entity).unwrap();
+        draw.updates.push(Update::Texture {
+            id: 100,
+            size: [4, 4],
+            data: vec![255; 64],
+            atlas: false,
+        });
+        draw.commands = vec![
+            pixel_widgets::draw::Command::Textured {
+                texture: 100,
+                offset: 0,
+                count: 6,
+            },
+            pixel_widgets::draw::Command::Colored { offset: 6, count: 6 },
+            pixel_widgets::draw::Command::Colored { offset: 12, count: 6 },
+        ];
+        app.update();
+
+        calls.extend(take_calls(&app));
+        let texture = |width| {
+            calls
+                .iter()
+                .find_map(|call| match call {
+                    ResourceCall::CreateTexture { texture, descriptor } if descriptor.size.width == width => {
+                        Some(*texture)
+                    }
+                    _ => None,
+                })
+                .unwrap()
+        };
+        // the colored draws share the white texture, and are merged into a single draw
+        let bound = bound_textures(&app, PIXEL_WIDGETS, &calls);
+        assert_eq!(bound, vec![texture(4), texture(1)]);
+        let render_graph = app.world.get_resource::<RenderGraph>().unwrap();
+        let node = render_graph.get_node::<UiNode>(PIXEL_WIDGETS).unwrap();
+        assert_eq!(
+            node.command_buffer.lock().unwrap().last(),
+            Some(&RenderCommand::Draw {
+                vertices: 6..18,
+                instances: 0..1,
+            })
+        );
+    }
+
+    #[test]
+    fn stylesheets_with_the_same_texture_id_bind_their_own_textures() {
+        let mut app = render_app(UiPlugin::default());
+        let first = spawn_counter(&mut app.world);
+        let second = spawn_counter(&mut app.world);
+        app.update();
+        take_calls(&app);
+
+        // both stylesheets have a texture 100, but of a different size
+        for &(entity, size) in [(first, 4), (second, 8)].iter() {
+            let mut draw = app.world.get_mut::<UiDraw>(entity).unwrap();
+            draw.updates.push(Update::Texture {
+                id: 100,
+                size: [size, size],
+                data: vec![255; (size * size * 4) as usize],
+                atlas: false,
+            });
+            draw.commands = vec![pixel_widgets::draw::Command::Textured {
+                texture: 100,
+                offset: 0,
+                count: 6,
+            }];
+        }
+        app.update();
+
+        let calls = take_calls(&app);
+        let texture_of_width = |width| {
+            calls
+                .iter()
+                .find_map(|call| match call {
+                    ResourceCall::CreateTexture { texture, descriptor } if descriptor.size.width == width => {
+                        Some(*texture)
+                    }
+                    _ => None,
+                })
+                .unwrap()
+        };
+        assert_eq!(
+            bound_textures(&app, PIXEL_WIDGETS, &calls),
+            vec![texture_of_width(4), texture_of_width(8)]
+        );
+    }
+
+    #[test]
+    fn swapping_the_stylesheet_skips_stale_draws() {
+        let mut app = render_app(UiPlugin::default());
+        let entity = spawn_counter(&mut app.world);
+        app.update();
+
+        let textured = vec![pixel_widgets::draw::Command::Textured {
+            texture: 100,
+            offset: 0,
+            count: 6,
+        }];
+        let mut draw = app.world.get_mut::<UiDraw>(entity).unwrap();
+        draw.updates.push(Update::Texture {
+            id: 100,
+            size: [4, 4],
+            data: vec![255; 64],
+            atlas: false,
+        });
+        draw.commands = textured.clone();
+        app.update();
+
+        // the new stylesheet has no texture 100, the ui is redrawn with it
+        app.world.entity_mut(entity).insert(StyleOverride::new(style()));
+        app.update();
+        assert_eq!(
+            drawn_vertex_buffers(&app, PIXEL_WIDGETS),
+            vec![app.world.get::<UiDraw>(entity).unwrap().vertices.unwrap()]
+        );
+
+        // draws and updates that still refer to the old texture are skipped
+        let mut draw = app.world.get_mut::<UiDraw>(entity).unwrap();
+        draw.updates.push(Update::TextureSubresource {
+            id: 100,
+            offset: [0, 0],
+            size: [4, 4],
+            data: vec![0; 64],
+        });
+        draw.commands = textured;
+        app.update();
+        let render_graph = app.world.get_resource::<RenderGraph>().unwrap();
+        let node = render_graph.get_node::<UiNode>(PIXEL_WIDGETS).unwrap();
+        let command_buffer = node.command_buffer.lock().unwrap();
+        assert!(!command_buffer
+            .iter()
+            .any(|command| matches!(command, RenderCommand::Draw { .. })));
+    }
+
+    #[test]
+    fn replaced_samplers_are_freed_after_the_release_delay() {
+        let mut app = render_app(UiPlugin::default());
+        spawn_counter(&mut app.world);
+        app.update();
+        let sampler = take_calls(&app)
+            .into_iter()
+            .find_map(|call| match call {
+                ResourceCall::CreateSampler { sampler, .. } => Some(sampler),
+                _ => None,
+            })
+            .unwrap();
+
+        // changing the filter replaces the sampler
+        app.world.get_resource_mut::<UiRenderSettings>().unwrap().filter = FilterMode::Nearest;
+        app.update();
+        for _ in 0..UiRenderSettings::default().texture_release_delay {
+            app.update();
+            assert!(!take_calls(&app).contains(&ResourceCall::RemoveSampler(sampler)));
+        }
+        app.update();
+        assert!(take_calls(&app).contains(&ResourceCall::RemoveSampler(sampler)));
+    }
+
+    #[test]
+    fn texture_bind_groups_are_only_created_when_not_drawn_with_last_frame() {
+        let mut app = render_app(UiPlugin::default());
+        spawn_counter(&mut app.world);
+        app.update();
+        assert!(!created_texture_bind_groups(&take_calls(&app)).is_empty());
+
+        // the bind groups were set in last frame's pass, so bevy didn't free them
+        app.update();
+        assert!(created_texture_bind_groups(&take_calls(&app)).is_empty());
+    }
+
+    #[test]
+    fn texture_target_draws_its_own_uis() {
+        let target = UiTextureTarget::new(target_texture(), 400, 300);
+        let mut app = render_app(UiPlugin::builder().texture_target(target).build());
+        let window_ui = spawn_counter(&mut app.world);
+        let texture_ui = spawn_counter(&mut app.world);
+        app.world
+            .entity_mut(texture_ui)
+            .insert(UiRenderTarget(target_texture()));
+        app.update();
+
+        let (color, size) = take_calls(&app)
+            .into_iter()
+            .find_map(|call| match call {
+                ResourceCall::CreateTexture { texture, descriptor }
+                    if descriptor.usage.contains(TextureUsage::OUTPUT_ATTACHMENT)
+                        && descriptor.format == TextureFormat::Bgra8UnormSrgb =>
+                {
+                    Some((texture, descriptor.size))
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!((size.width, size.height), (400, 300));
+        let context = app.world.get_resource::<Box<dyn RenderResourceContext>>().unwrap();
+        assert_eq!(
+            context.get_asset_resource(&target_texture(), TEXTURE_ASSET_INDEX),
+            Some(RenderResourceId::Texture(color))
+        );
+
+        let vertices = |entity| app.world.get::<UiDraw>(entity).unwrap().vertices.unwrap();
+        assert_eq!(drawn_vertex_buffers(&app, PIXEL_WIDGETS), vec![vertices(window_ui)]);
+        assert_eq!(
+            drawn_vertex_buffers(&app, texture_target_node(0)),
+            vec![vertices(texture_ui)]
+        );
+    }
+
+    #[test]
+    fn texture_target_sampler_uses_its_own_filter() {
+        let target = UiTextureTarget::new(target_texture(), 400, 300).with_filter(FilterMode::Nearest);
+        let mut app = render_app(UiPlugin::builder().texture_target(target).build());
+        // the ui textures themselves keep the linear default
+        assert_eq!(
+            app.world.get_resource::<UiRenderSettings>().unwrap().filter,
+            FilterMode::Linear
+        );
+        let texture_ui = spawn_counter(&mut app.world);
+        app.world
+            .entity_mut(texture_ui)
+            .insert(UiRenderTarget(target_texture()));
+        app.update();
+
+        let context = app.world.get_resource::<Box<dyn RenderResourceContext>>().unwrap();
+        let target_sampler = context.get_asset_resource(&target_texture(), SAMPLER_ASSET_INDEX);
+        let filters = take_calls(&app).into_iter().find_map(|call| match call {
+            ResourceCall::CreateSampler {
+                sampler,
+                mag_filter,
+                min_filter,
+                ..
+            } if Some(RenderResourceId::Sampler(sampler)) == target_sampler => Some((mag_filter, min_filter)),
+            _ => None,
+        });
+        assert_eq!(filters, Some((FilterMode::Nearest, FilterMode::Nearest)));
+    }
+
+    #[test]
+    fn texture_target_ui_uses_a_transparent_clear() {
+        let target = UiTextureTarget::new(target_texture(), 400, 300);
+        let mut app = render_app(UiPlugin::builder().texture_target(target).build());
+        let texture_ui = spawn_counter(&mut app.world);
+        app.world
+            .entity_mut(texture_ui)
+            .insert(UiRenderTarget(target_texture()));
+        app.update();
+
+        let render_graph = app.world.get_resource::<RenderGraph>().unwrap();
+        let load = |label: NodeLabel| {
+            let node = render_graph.get_node::<UiNode>(label).unwrap();
+            node.descriptor.color_attachments[0].ops.load
+        };
+        assert_eq!(load(texture_target_node(0).into()), LoadOp::Clear(Color::NONE));
+        // the window already shows the scene
+        assert_eq!(load(PIXEL_WIDGETS.into()), LoadOp::Load);
+
+        // the alpha of the widgets is blended over the transparent background
+        let node = render_graph.get_node::<UiNode>(texture_target_node(0)).unwrap();
+        let pipelines = app.world.get_resource::<Assets<PipelineDescriptor>>().unwrap();
+        let command_buffer = node.command_buffer.lock().unwrap();
+        let mut set_pipelines = command_buffer
+            .iter()
+            .filter_map(|command| match command {
+                RenderCommand::SetPipeline { pipeline } => pipelines.get(pipeline),
+                _ => None,
+            })
+            .peekable();
+        assert!(set_pipelines.peek().is_some());
+        assert!(
+            set_pipelines.all(|pipeline| pipeline.color_target_states[0].alpha_blend == UiBlend::STRAIGHT_ALPHA.alpha)
+        );
+    }
+
+    #[test]
+    fn uis_are_no_longer_drawn_after_a_sample_count_change() {
+        let mut app = render_app(UiPlugin::default());
+        let entity = spawn_counter(&mut app.world);
+        app.update();
+        assert!(!drawn_vertex_buffers(&app, PIXEL_WIDGETS).is_empty());
+
+        app.world.insert_resource(Msaa { samples: 4 });
+        app.world.get_mut::<Ui<Counter>>(entity).unwrap().rebuild();
+        app.update();
+        app.update();
+        assert!(drawn_vertex_buffers(&app, PIXEL_WIDGETS).is_empty());
+    }
+
+    #[test]
+    fn texture_target_resolves_multisampled_uis() {
+        let target = UiTextureTarget::new(target_texture(), 400, 300);
+        let plugin = UiPlugin::builder().texture_target(target).build();
+        let mut app = render_app_with_msaa(plugin, Msaa { samples: 4 });
+        let texture_ui = spawn_counter(&mut app.world);
+        app.world
+            .entity_mut(texture_ui)
+            .insert(UiRenderTarget(target_texture()));
+        app.update();
+
+        let render_graph = app.world.get_resource::<RenderGraph>().unwrap();
+        let node = render_graph.get_node::<UiNode>(texture_target_node(0)).unwrap();
+        assert_eq!(node.descriptor.sample_count, 4);
+        assert!(node.descriptor.color_attachments[0].resolve_target.is_some());
+
+        // the uis are drawn into a multisampled texture, which is resolved into the one materials sample
+        let color_textures = take_calls(&app)
+            .into_iter()
+            .filter_map(|call| match call {
+                ResourceCall::CreateTexture { texture, descriptor }
+                    if descriptor.format == TextureFormat::Bgra8UnormSrgb && descriptor.size.width == 400 =>
+                {
+                    Some((texture, descriptor.sample_count))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(color_textures.len(), 2);
+        let (resolved, _) = color_textures.iter().find(|&&(_, samples)| samples == 1).unwrap();
+        assert!(color_textures.iter().any(|&(_, samples)| samples == 4));
+        let context = app.world.get_resource::<Box<dyn RenderResourceContext>>().unwrap();
+        assert_eq!(
+            context.get_asset_resource(&target_texture(), TEXTURE_ASSET_INDEX),
+            Some(RenderResourceId::Texture(*resolved))
+        );
+    }
+
+    #[test]
+    fn secondary_window_draws_its_own_uis() {
+        let mut app = render_app(UiPlugin::builder().secondary_windows(true).build());
+        let window = add_secondary_window(&mut app, 400, 300);
+        let primary_ui = spawn_counter(&mut app.world);
+        let secondary_ui = spawn_counter(&mut app.world);
+        app.world.entity_mut(secondary_ui).insert(UiWindow(window));
+        // the pass of the window is added during the first update
+        app.update();
+        app.update();
+
+        let render_graph = app.world.get_resource::<RenderGraph>().unwrap();
+        let node = render_graph.get_node::<UiNode>(window_node(window)).unwrap();
+        // nothing else draws into the window
+        let clear_color = app.world.get_resource::<ClearColor>().unwrap().0;
+        assert_eq!(
+            node.descriptor.color_attachments[0].ops.load,
+            LoadOp::Clear(clear_color)
+        );
+
+        let vertices = |entity| app.world.get::<UiDraw>(entity).unwrap().vertices.unwrap();
+        assert_eq!(drawn_vertex_buffers(&app, PIXEL_WIDGETS), vec![vertices(primary_ui)]);
+        assert_eq!(
+            drawn_vertex_buffers(&app, window_node(window)),
+            vec![vertices(secondary_ui)]
+        );
+    }
+
+    #[test]
+    fn secondary_windows_get_no_passes_by_default() {
+        let mut app = render_app(UiPlugin::default());
+        app.update();
+        let node_count = |app: &App| app.world.get_resource::<RenderGraph>().unwrap().iter_nodes().count();
+        let nodes = node_count(&app);
+
+        let window = add_secondary_window(&mut app, 400, 300);
+        app.update();
+        app.update();
+
+        assert_eq!(node_count(&app), nodes);
+        let render_graph = app.world.get_resource::<RenderGraph>().unwrap();
+        assert!(render_graph.get_node_state(window_node(window)).is_err());
+    }
+
+    #[test]
+    fn app_rendered_window_keeps_its_contents() {
+        let mut app = render_app(UiPlugin::builder().depth(UiDepth::Disabled).build());
+        let window = add_secondary_window(&mut app, 400, 300);
+        app.world
+            .get_resource_mut::<RenderGraph>()
+            .unwrap()
+            .add_node("second_window_swap_chain", WindowSwapChainNode::new(window));
+        add_ui_window_pass(
+            &mut app.world,
+            window,
+            UiWindowNodes {
+                swap_chain: "second_window_swap_chain".into(),
+                sampled_color_attachment: None,
+                depth: None,
+                pass: base::node::MAIN_PASS.into(),
+            },
+        );
+        let node_count = app.world.get_resource::<RenderGraph>().unwrap().iter_nodes().count();
+        let ui = spawn_counter(&mut app.world);
+        app.world.entity_mut(ui).insert(UiWindow(window));
+        app.update();
+
+        let render_graph = app.world.get_resource::<RenderGraph>().unwrap();
+        // the app's swap chain is used, no other nodes are added for the window
+        assert_eq!(render_graph.iter_nodes().count(), node_count);
+        let node = render_graph.get_node::<UiNode>(window_node(window)).unwrap();
+        assert_eq!(node.descriptor.color_attachments[0].ops.load, LoadOp::Load);
+        let state = render_graph.get_node_state(window_node(window)).unwrap();
+        assert!(state.validate_input_slots().is_ok());
+        let main_pass = render_graph.get_node_id(base::node::MAIN_PASS).unwrap();
+        let inputs = &state.edges.input_edges;
+        assert!(inputs.iter().any(|edge| edge.get_output_node() == main_pass));
+
+        let vertices = app.world.get::<UiDraw>(ui).unwrap().vertices.unwrap();
+        assert_eq!(drawn_vertex_buffers(&app, window_node(window)), vec![vertices]);
+    }
+
+    #[test]
+    fn ui_is_drawn_once_its_stylesheet_is_loaded() {
+        let mut app = render_app(UiPlugin::default());
+        let stylesheet = stylesheet_handle();
+        let ui = spawn_counter_with_stylesheet(&mut app.world, stylesheet.clone());
+        app.update();
+        app.update();
+
+        assert!(!app.world.get::<Ui<Counter>>(ui).unwrap().is_style_loaded());
+        let draw = app.world.get::<UiDraw>(ui).unwrap();
+        assert!(draw.vertices.is_none());
+        assert!(draw.updates.is_empty());
+        assert!(drawn_vertex_buffers(&app, PIXEL_WIDGETS).is_empty());
+
+        load_stylesheet(&mut app, &stylesheet, style());
+        app.update();
+
+        assert!(app.world.get::<Ui<Counter>>(ui).unwrap().is_style_loaded());
+        let vertices = app.world.get::<UiDraw>(ui).unwrap().vertices.unwrap();
+        assert_eq!(drawn_vertex_buffers(&app, PIXEL_WIDGETS), vec![vertices]);
+    }
+}
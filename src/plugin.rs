@@ -1,63 +1,181 @@
+use std::borrow::Cow;
+
 use bevy::prelude::*;
 use bevy::render::pass::*;
-use bevy::render::pipeline::PipelineDescriptor;
+use bevy::render::pipeline::{PipelineDescriptor, PolygonMode, PrimitiveTopology};
 use bevy::render::render_graph::*;
+use bevy::render::texture::{TextureDescriptor, TextureFormat, TextureUsage};
+use bevy::window::{WindowCreated, WindowId};
 
-use crate::pipeline::{build_ui_pipeline, UI_PIPELINE_HANDLE};
-use crate::pixel_widgets_node::UiNode;
+use crate::diagnostics::UiDiagnostics;
+use crate::filter::{UiColorFilter, UiGamma};
+use crate::modal::ModalStack;
+use crate::pipeline::{
+    build_ui_pipeline, UiBlend, UiWireframe, UI_PIPELINE_HANDLE, UI_TARGET_PIPELINE_HANDLE,
+    UI_WIREFRAME_PIPELINE_HANDLE,
+};
+use crate::pixel_widgets_node::{ReleasedBuffers, UiNode, UiRenderSettings};
 use crate::style::{Stylesheet, StylesheetLoader};
+use crate::target::{PassTarget, UiTextureTarget, UiTextureTargets};
+use crate::update::UiInputSettings;
+
+pub(crate) const PIXEL_WIDGETS: &str = "pixel_widgets";
+
+/// Sets up the rendering of uis. One plugin serves all model types, adding it more than once has no effect.
+/// Every model type needs its own system that calls `UpdateUiSystemParams::update` though.
+///
+/// The uis of all model types are drawn by the same render node, ordered by their `UiLayer`.
+/// The order of uis on the same layer is unspecified.
+///
+/// The render pass is set up for the `Msaa` sample count at the time the plugin is added, like bevy's main pass.
+/// Changing the sample count afterwards isn't supported: an error is logged and the uis aren't drawn anymore,
+/// so insert `Msaa` before adding the plugin.
+///
+/// Every texture target gets a render pass of its own, which draws the uis with its `UiRenderTarget`.
+/// These passes use the same sample count, they resolve into the texture of the target.
+/// Secondary windows only get a render pass with `secondary_windows` or `add_ui_window_pass`, see `UiWindow`.
+pub struct UiPlugin {
+    pub depth: UiDepth,
+    /// How the ui vertices are assembled into primitives. pixel_widgets always generates triangle lists,
+    /// other topologies reinterpret those vertices, for example as line segments for a wireframe look.
+    pub topology: PrimitiveTopology,
+    pub blend: UiBlend,
+    pub texture_targets: Vec<UiTextureTarget>,
+    /// Gives every window that is created after the plugin was added a swap chain and a ui pass of its own, which
+    /// clears the window to the `ClearColor` before drawing its uis. Only for windows that show nothing but uis,
+    /// windows that the app renders itself need `add_ui_window_pass` instead.
+    pub secondary_windows: bool,
+}
 
-const PIXEL_WIDGETS: &str = "pixel_widgets";
+/// How the UI pass uses the depth buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UiDepth {
+    /// Depth test against the `MAIN_DEPTH_TEXTURE` of the main pass.
+    Test,
+    /// No depth attachment at all, for pure 2D apps that don't have a `MAIN_DEPTH_TEXTURE` node.
+    Disabled,
+    /// Always draw on top of the scene, for HUDs over 3D scenes. The pass keeps the `MAIN_DEPTH_TEXTURE` attached,
+    /// but the depth test always passes with `CompareFunction::Always`, so the UI is never clipped by closer
+    /// geometry. Depth writes are off and the depth is loaded instead of cleared, so the scene depth stays intact
+    /// for later passes.
+    Overlay,
+}
+
+impl UiDepth {
+    /// Whether passes with this depth mode attach a depth texture.
+    pub(crate) fn has_attachment(self) -> bool {
+        matches!(self, UiDepth::Test | UiDepth::Overlay)
+    }
+}
+
+impl Default for UiPlugin {
+    fn default() -> Self {
+        Self {
+            depth: UiDepth::Test,
+            topology: PrimitiveTopology::TriangleList,
+            blend: UiBlend::default(),
+            texture_targets: Vec::new(),
+            secondary_windows: false,
+        }
+    }
+}
+
+impl UiPlugin {
+    pub fn builder() -> UiPluginBuilder {
+        UiPluginBuilder::default()
+    }
+}
 
-pub struct UiPlugin;
+/// Builds a `UiPlugin` with non default settings.
+#[derive(Default)]
+pub struct UiPluginBuilder {
+    plugin: UiPlugin,
+}
+
+impl UiPluginBuilder {
+    pub fn depth(mut self, depth: UiDepth) -> Self {
+        self.plugin.depth = depth;
+        self
+    }
+
+    pub fn topology(mut self, topology: PrimitiveTopology) -> Self {
+        self.plugin.topology = topology;
+        self
+    }
+
+    pub fn blend(mut self, blend: UiBlend) -> Self {
+        self.plugin.blend = blend;
+        self
+    }
+
+    /// Adds a texture that uis can be drawn into with `UiRenderTarget`.
+    pub fn texture_target(mut self, target: UiTextureTarget) -> Self {
+        self.plugin.texture_targets.push(target);
+        self
+    }
+
+    pub fn secondary_windows(mut self, secondary_windows: bool) -> Self {
+        self.plugin.secondary_windows = secondary_windows;
+        self
+    }
+
+    pub fn build(self) -> UiPlugin {
+        self.plugin
+    }
+}
 
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut AppBuilder) {
+        // everything is shared between all model types, so only the first `UiPlugin` sets things up
+        if app.world().contains_resource::<UiPluginAdded>() {
+            return;
+        }
+        app.insert_resource(UiPluginAdded);
+
         app.add_asset::<Stylesheet>();
         app.init_asset_loader::<StylesheetLoader>();
+        app.init_resource::<UiColorFilter>();
+        app.init_resource::<UiGamma>();
+        app.init_resource::<UiInputSettings>();
+        app.init_resource::<ModalStack>();
+        app.init_resource::<UiRenderSettings>();
+        app.init_resource::<ReleasedBuffers>();
+        app.init_resource::<UiWireframe>();
+        app.insert_resource(UiTextureTargets(self.texture_targets.clone()));
+        app.add_startup_system(UiDiagnostics::setup_system.system());
 
         let world = app.world_mut();
 
-        #[allow(clippy::redundant_pattern_matching)] // needed for the type annotation
-        if let Result::<&UiNode, _>::Err(_) = world.get_resource::<RenderGraph>().unwrap().get_node(PIXEL_WIDGETS) {
-            let msaa = world.get_resource::<Msaa>().unwrap();
-            let msaa_samples = msaa.samples;
+        // without a render graph the uis are updated, but not rendered. useful for tests and servers.
+        if world.get_resource::<RenderGraph>().is_none() {
+            return;
+        }
 
-            let pass_descriptor = PassDescriptor {
-                color_attachments: vec![msaa.color_attachment_descriptor(
-                    TextureAttachment::Input("color_attachment".to_string()),
-                    TextureAttachment::Input("color_resolve_target".to_string()),
-                    Operations {
-                        load: LoadOp::Load,
-                        store: true,
-                    },
-                )],
-                depth_stencil_attachment: Some(RenderPassDepthStencilAttachmentDescriptor {
-                    attachment: TextureAttachment::Input("depth".to_string()),
-                    depth_ops: Some(Operations {
-                        load: LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: None,
-                }),
-                sample_count: msaa.samples,
-            };
+        let msaa = world.get_resource::<Msaa>().unwrap();
+        let msaa_samples = msaa.samples;
 
-            let mut render_graph = world.get_resource_mut::<RenderGraph>().unwrap();
-            render_graph.add_system_node(PIXEL_WIDGETS, UiNode::new(pass_descriptor));
-            render_graph
-                .add_slot_edge(
-                    base::node::PRIMARY_SWAP_CHAIN,
-                    WindowSwapChainNode::OUT_TEXTURE,
-                    PIXEL_WIDGETS,
-                    if msaa_samples > 1 {
-                        "color_resolve_target"
-                    } else {
-                        "color_attachment"
-                    },
-                )
-                .unwrap();
+        // the ui is drawn on top of the scene in the primary window
+        let pass_descriptor = window_pass_descriptor(msaa, self.depth, LoadOp::Load);
+
+        let mut render_graph = world.get_resource_mut::<RenderGraph>().unwrap();
+        render_graph.add_system_node(
+            PIXEL_WIDGETS,
+            UiNode::new(pass_descriptor, PassTarget::Window(WindowId::primary())),
+        );
+        render_graph
+            .add_slot_edge(
+                base::node::PRIMARY_SWAP_CHAIN,
+                WindowSwapChainNode::OUT_TEXTURE,
+                PIXEL_WIDGETS,
+                if msaa_samples > 1 {
+                    "color_resolve_target"
+                } else {
+                    "color_attachment"
+                },
+            )
+            .unwrap();
 
+        if self.depth.has_attachment() {
             render_graph
                 .add_slot_edge(
                     base::node::MAIN_DEPTH_TEXTURE,
@@ -66,26 +184,383 @@ impl Plugin for UiPlugin {
                     "depth",
                 )
                 .unwrap();
+        }
 
-            if msaa_samples > 1 {
-                render_graph
-                    .add_slot_edge(
-                        base::node::MAIN_SAMPLED_COLOR_ATTACHMENT,
-                        WindowSwapChainNode::OUT_TEXTURE,
-                        PIXEL_WIDGETS,
-                        "color_attachment",
-                    )
-                    .unwrap();
-            }
+        if msaa_samples > 1 {
             render_graph
-                .add_node_edge(base::node::MAIN_PASS, PIXEL_WIDGETS)
+                .add_slot_edge(
+                    base::node::MAIN_SAMPLED_COLOR_ATTACHMENT,
+                    WindowSwapChainNode::OUT_TEXTURE,
+                    PIXEL_WIDGETS,
+                    "color_attachment",
+                )
                 .unwrap();
+        }
+        render_graph
+            .add_node_edge(base::node::MAIN_PASS, PIXEL_WIDGETS)
+            .unwrap();
 
-            let pipeline = build_ui_pipeline(&mut world.get_resource_mut::<Assets<Shader>>().unwrap());
-            world
-                .get_resource_mut::<Assets<PipelineDescriptor>>()
-                .unwrap()
-                .set_untracked(UI_PIPELINE_HANDLE, pipeline);
+        for (i, target) in self.texture_targets.iter().enumerate() {
+            let name = texture_target_node(i);
+            let pass_descriptor = PassDescriptor {
+                // the attachments are replaced with the textures of the target once they have been created
+                color_attachments: vec![RenderPassColorAttachmentDescriptor {
+                    attachment: TextureAttachment::Name(name.clone()),
+                    resolve_target: if msaa_samples > 1 {
+                        Some(TextureAttachment::Name(name.clone()))
+                    } else {
+                        None
+                    },
+                    ops: Operations {
+                        load: target.load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: if self.depth.has_attachment() {
+                    Some(RenderPassDepthStencilAttachmentDescriptor {
+                        attachment: TextureAttachment::Name(name.clone()),
+                        depth_ops: Some(Operations {
+                            load: LoadOp::Clear(1.0),
+                            store: true,
+                        }),
+                        stencil_ops: None,
+                    })
+                } else {
+                    None
+                },
+                sample_count: msaa_samples,
+            };
+            render_graph.add_system_node(
+                name.clone(),
+                UiNode::new(pass_descriptor, PassTarget::Texture(target.clone())),
+            );
+            // the scene may show the texture, so the uis are drawn into it first
+            render_graph.add_node_edge(name, base::node::MAIN_PASS).unwrap();
+        }
+
+        let pipeline = build_ui_pipeline(
+            &mut world.get_resource_mut::<Assets<Shader>>().unwrap(),
+            self.depth,
+            self.topology,
+            self.blend.clone(),
+        );
+        let mut wireframe_pipeline = pipeline.clone();
+        wireframe_pipeline.primitive.polygon_mode = PolygonMode::Line;
+        let mut target_pipeline = pipeline.clone();
+        target_pipeline.color_target_states[0].alpha_blend = UiBlend::STRAIGHT_ALPHA.alpha;
+
+        let mut pipelines = world.get_resource_mut::<Assets<PipelineDescriptor>>().unwrap();
+        pipelines.set_untracked(UI_PIPELINE_HANDLE, pipeline);
+        pipelines.set_untracked(UI_WIREFRAME_PIPELINE_HANDLE, wireframe_pipeline);
+        pipelines.set_untracked(UI_TARGET_PIPELINE_HANDLE, target_pipeline);
+
+        app.insert_resource(WindowPassSettings { depth: self.depth });
+        if self.secondary_windows {
+            app.add_system(add_window_passes.system());
         }
     }
 }
+
+/// Marks that a `UiPlugin` was added, with or without a render graph.
+struct UiPluginAdded;
+
+/// How the passes of secondary windows are set up.
+struct WindowPassSettings {
+    depth: UiDepth,
+}
+
+/// The render graph nodes of a secondary window that the app renders itself, see `add_ui_window_pass`.
+#[derive(Debug, Clone)]
+pub struct UiWindowNodes {
+    /// The `WindowSwapChainNode` of the window.
+    pub swap_chain: Cow<'static, str>,
+    /// The `WindowTextureNode` of the multisampled color attachment, needed when `Msaa` has more than one sample.
+    pub sampled_color_attachment: Option<Cow<'static, str>>,
+    /// The `WindowTextureNode` of the depth texture, only needed with `UiDepth::Test`.
+    pub depth: Option<Cow<'static, str>>,
+    /// The pass that renders the scene of the window, the uis are drawn after it.
+    pub pass: Cow<'static, str>,
+}
+
+/// Draws the uis of the secondary window `window` on top of a window that the app renders itself, like the primary
+/// window. The ui pass loads what `nodes.pass` rendered instead of clearing it, and uses the swap chain and textures
+/// of the app instead of adding its own.
+///
+/// If `UiPlugin::secondary_windows` is enabled, call this before the window is created, it won't get a clearing
+/// pass of its own then.
+pub fn add_ui_window_pass(world: &mut World, window: WindowId, nodes: UiWindowNodes) {
+    let depth = world.get_resource::<WindowPassSettings>().unwrap().depth;
+    let msaa = world.get_resource::<Msaa>().unwrap();
+    let pass_descriptor = window_pass_descriptor(msaa, depth, LoadOp::Load);
+    let msaa_samples = msaa.samples;
+    let sampled_color_attachment = nodes.sampled_color_attachment.as_deref().filter(|_| msaa_samples > 1);
+    assert!(
+        msaa_samples == 1 || sampled_color_attachment.is_some(),
+        "a multisampled ui pass needs the sampled color attachment of the window"
+    );
+    let depth_node = nodes.depth.as_deref().filter(|_| depth.has_attachment());
+    assert!(
+        !depth.has_attachment() || depth_node.is_some(),
+        "a ui pass with depth needs the depth texture of the window"
+    );
+
+    let mut render_graph = world.get_resource_mut::<RenderGraph>().unwrap();
+    let name = window_node(window);
+    render_graph.add_system_node(name.clone(), UiNode::new(pass_descriptor, PassTarget::Window(window)));
+    connect_window_pass(
+        &mut render_graph,
+        &name,
+        msaa_samples,
+        &nodes.swap_chain,
+        sampled_color_attachment,
+        depth_node,
+        Some(&*nodes.pass),
+    );
+}
+
+/// The pass of a window's ui. Its attachments are the input slots `color_attachment`, `color_resolve_target` with
+/// multisampling, and `depth` with `UiDepth::Test` and `UiDepth::Overlay`.
+pub(crate) fn window_pass_descriptor(msaa: &Msaa, depth: UiDepth, load: LoadOp<Color>) -> PassDescriptor {
+    PassDescriptor {
+        color_attachments: vec![msaa.color_attachment_descriptor(
+            TextureAttachment::Input("color_attachment".to_string()),
+            TextureAttachment::Input("color_resolve_target".to_string()),
+            Operations { load, store: true },
+        )],
+        depth_stencil_attachment: match depth {
+            UiDepth::Test => Some(RenderPassDepthStencilAttachmentDescriptor {
+                attachment: TextureAttachment::Input("depth".to_string()),
+                depth_ops: Some(Operations {
+                    load: LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+            UiDepth::Overlay => Some(RenderPassDepthStencilAttachmentDescriptor {
+                attachment: TextureAttachment::Input("depth".to_string()),
+                depth_ops: Some(Operations {
+                    load: LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+            UiDepth::Disabled => None,
+        },
+        sample_count: msaa.samples,
+    }
+}
+
+/// Adds a ui pass for every new window other than the primary one, which already has its pass, when
+/// `UiPlugin::secondary_windows` is enabled. Nothing else draws into these windows, so the pass gets the swap chain
+/// and depth texture of the window to itself and clears them first.
+/// Windows that already have a pass from `add_ui_window_pass` are left alone.
+fn add_window_passes(
+    mut window_created_events: EventReader<WindowCreated>,
+    settings: Res<WindowPassSettings>,
+    msaa: Res<Msaa>,
+    clear_color: Res<ClearColor>,
+    mut render_graph: ResMut<RenderGraph>,
+) {
+    for event in window_created_events.iter().filter(|event| !event.id.is_primary()) {
+        let name = window_node(event.id);
+        if render_graph.get_node_state(name.clone()).is_ok() {
+            continue;
+        }
+        let pass_descriptor = window_pass_descriptor(&msaa, settings.depth, LoadOp::Clear(clear_color.0));
+        render_graph.add_system_node(name.clone(), UiNode::new(pass_descriptor, PassTarget::Window(event.id)));
+
+        let swap_chain = format!("{}_swap_chain", name);
+        render_graph.add_node(swap_chain.clone(), WindowSwapChainNode::new(event.id));
+
+        let depth = if settings.depth.has_attachment() {
+            let depth = format!("{}_depth", name);
+            let descriptor = TextureDescriptor {
+                sample_count: msaa.samples,
+                format: TextureFormat::Depth32Float,
+                usage: TextureUsage::OUTPUT_ATTACHMENT,
+                ..TextureDescriptor::default()
+            };
+            render_graph.add_node(depth.clone(), WindowTextureNode::new(event.id, descriptor));
+            Some(depth)
+        } else {
+            None
+        };
+
+        let sampled = if msaa.samples > 1 {
+            let sampled = format!("{}_sampled", name);
+            let descriptor = TextureDescriptor {
+                sample_count: msaa.samples,
+                format: TextureFormat::Bgra8UnormSrgb,
+                usage: TextureUsage::OUTPUT_ATTACHMENT,
+                ..TextureDescriptor::default()
+            };
+            render_graph.add_node(sampled.clone(), WindowTextureNode::new(event.id, descriptor));
+            Some(sampled)
+        } else {
+            None
+        };
+
+        connect_window_pass(
+            &mut render_graph,
+            &name,
+            msaa.samples,
+            &swap_chain,
+            sampled.as_deref(),
+            depth.as_deref(),
+            None,
+        );
+    }
+}
+
+/// Connects the window nodes to the input slots of the ui pass `name`, and runs the pass after `after`.
+fn connect_window_pass(
+    render_graph: &mut RenderGraph,
+    name: &str,
+    msaa_samples: u32,
+    swap_chain: &str,
+    sampled_color_attachment: Option<&str>,
+    depth: Option<&str>,
+    after: Option<&str>,
+) {
+    render_graph
+        .add_slot_edge(
+            swap_chain.to_string(),
+            WindowSwapChainNode::OUT_TEXTURE,
+            name.to_string(),
+            if msaa_samples > 1 {
+                "color_resolve_target"
+            } else {
+                "color_attachment"
+            },
+        )
+        .unwrap();
+    if let Some(sampled_color_attachment) = sampled_color_attachment {
+        render_graph
+            .add_slot_edge(
+                sampled_color_attachment.to_string(),
+                WindowTextureNode::OUT_TEXTURE,
+                name.to_string(),
+                "color_attachment",
+            )
+            .unwrap();
+    }
+    if let Some(depth) = depth {
+        render_graph
+            .add_slot_edge(
+                depth.to_string(),
+                WindowTextureNode::OUT_TEXTURE,
+                name.to_string(),
+                "depth",
+            )
+            .unwrap();
+    }
+    if let Some(after) = after {
+        render_graph.add_node_edge(after.to_string(), name.to_string()).unwrap();
+    }
+}
+
+/// Name of the render graph node that draws into the secondary window `id`.
+pub(crate) fn window_node(id: WindowId) -> String {
+    format!("{}_window_{}", PIXEL_WIDGETS, id)
+}
+
+/// Name of the render graph node that draws into the texture target at `index`.
+pub(crate) fn texture_target_node(index: usize) -> String {
+    format!("{}_texture_{}", PIXEL_WIDGETS, index)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::AssetPlugin;
+    use bevy::core::CorePlugin;
+    use bevy::render::pipeline::CompareFunction;
+    use bevy::render::render_graph::base::BaseRenderGraphConfig;
+    use bevy::render::RenderPlugin;
+    use bevy::window::WindowPlugin;
+
+    use super::*;
+    use crate::test_util::target_texture;
+
+    #[test]
+    fn disabled_depth_builds_without_main_depth_texture() {
+        let mut app = App::build();
+        app.add_plugin(CorePlugin)
+            .add_plugin(WindowPlugin::default())
+            .add_plugin(AssetPlugin)
+            .add_plugin(RenderPlugin {
+                base_render_graph_config: Some(BaseRenderGraphConfig {
+                    add_main_depth_texture: false,
+                    connect_main_pass_to_main_depth_texture: false,
+                    ..Default::default()
+                }),
+            })
+            .add_plugin(UiPlugin::builder().depth(UiDepth::Disabled).build());
+
+        let render_graph = app.world().get_resource::<RenderGraph>().unwrap();
+        assert!(render_graph.get_node_state(base::node::MAIN_DEPTH_TEXTURE).is_err());
+        let node = render_graph.get_node_state(PIXEL_WIDGETS).unwrap();
+        assert!(node.input_slots.get_slot("depth").is_err());
+        assert!(node.validate_input_slots().is_ok());
+    }
+
+    #[test]
+    fn builder_settings_reach_the_pipeline_and_pass() {
+        let plugin = UiPlugin::builder()
+            .depth(UiDepth::Overlay)
+            .topology(PrimitiveTopology::LineList)
+            .blend(UiBlend::PREMULTIPLIED_ALPHA)
+            .texture_target(UiTextureTarget::new(target_texture(), 64, 32))
+            .secondary_windows(true)
+            .build();
+        assert_eq!(plugin.depth, UiDepth::Overlay);
+        assert_eq!(plugin.topology, PrimitiveTopology::LineList);
+        assert_eq!(plugin.blend, UiBlend::PREMULTIPLIED_ALPHA);
+        assert_eq!(plugin.texture_targets.len(), 1);
+        assert_eq!(plugin.texture_targets[0].texture, target_texture());
+        assert!(plugin.secondary_windows);
+
+        let mut app = App::build();
+        app.add_plugin(CorePlugin)
+            .add_plugin(WindowPlugin::default())
+            .add_plugin(AssetPlugin)
+            .add_plugin(RenderPlugin::default())
+            .add_plugin(plugin);
+
+        let pipelines = app.world().get_resource::<Assets<PipelineDescriptor>>().unwrap();
+        let pipeline = pipelines
+            .get(&UI_PIPELINE_HANDLE.clone().typed::<PipelineDescriptor>())
+            .unwrap();
+        assert_eq!(pipeline.primitive.topology, PrimitiveTopology::LineList);
+        let color_target = &pipeline.color_target_states[0];
+        assert_eq!(color_target.color_blend, UiBlend::PREMULTIPLIED_ALPHA.color);
+        assert_eq!(color_target.alpha_blend, UiBlend::PREMULTIPLIED_ALPHA.alpha);
+        let depth_stencil = pipeline.depth_stencil.as_ref().unwrap();
+        assert_eq!(depth_stencil.depth_compare, CompareFunction::Always);
+
+        let render_graph = app.world().get_resource::<RenderGraph>().unwrap();
+        let node = render_graph.get_node_state(PIXEL_WIDGETS).unwrap();
+        assert!(node.input_slots.get_slot("depth").is_ok());
+        assert!(render_graph.get_node_state(texture_target_node(0)).is_ok());
+    }
+
+    #[test]
+    fn headless_plugin_is_only_set_up_once() {
+        let target = UiTextureTarget::new(target_texture(), 64, 64);
+        let mut app = App::build();
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(UiPlugin::builder().texture_target(target).build());
+        app.world_mut()
+            .get_resource_mut::<Assets<Stylesheet>>()
+            .unwrap()
+            .add(Stylesheet {
+                style: crate::test_util::style(),
+            });
+        app.add_plugin(UiPlugin::default());
+
+        // the second plugin neither replaces the settings nor the assets of the first one
+        let targets = app.world().get_resource::<UiTextureTargets>().unwrap();
+        assert!(targets.get(&target_texture()).is_some());
+        assert_eq!(app.world().get_resource::<Assets<Stylesheet>>().unwrap().len(), 1);
+    }
+}
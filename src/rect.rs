@@ -0,0 +1,49 @@
+use bevy::math::Rect;
+use pixel_widgets::layout::Rectangle;
+
+/// Converts a rect in bevy's window coordinates, where y points up from the bottom of the window,
+/// to a `Rectangle` in ui coordinates, where y points down from the top.
+pub fn rectangle_from_window_rect(rect: Rect<f32>, window_height: f32) -> Rectangle {
+    Rectangle {
+        left: rect.left,
+        top: window_height - rect.top,
+        right: rect.right,
+        bottom: window_height - rect.bottom,
+    }
+}
+
+/// Converts a `Rectangle` in ui coordinates back to a rect in bevy's window coordinates.
+pub fn window_rect_from_rectangle(rectangle: Rectangle, window_height: f32) -> Rect<f32> {
+    Rect {
+        left: rectangle.left,
+        right: rectangle.right,
+        top: window_height - rectangle.top,
+        bottom: window_height - rectangle.bottom,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let rect = Rect {
+            left: 10.0,
+            right: 110.0,
+            top: 500.0,
+            bottom: 460.0,
+        };
+        let rectangle = rectangle_from_window_rect(rect, 600.0);
+        assert_eq!(
+            (rectangle.left, rectangle.top, rectangle.right, rectangle.bottom),
+            (10.0, 100.0, 110.0, 140.0)
+        );
+
+        let back = window_rect_from_rectangle(rectangle, 600.0);
+        assert_eq!(
+            (back.left, back.right, back.top, back.bottom),
+            (rect.left, rect.right, rect.top, rect.bottom)
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use bevy::input::keyboard::KeyCode;
+use pixel_widgets::event::Modifiers;
+use pixel_widgets::Model;
+
+/// A key combined with the modifiers that have to be held for it to trigger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Shortcut {
+    pub key: KeyCode,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub logo: bool,
+}
+
+/// Maps keyboard shortcuts to messages for all `Ui<M>` entities.
+/// Insert it as a resource and the messages are sent to the ui whenever the shortcut is pressed.
+pub struct ShortcutMap<M: Model> {
+    shortcuts: Vec<(Shortcut, Box<dyn Fn() -> M::Message + Send + Sync>)>,
+}
+
+impl Shortcut {
+    pub fn new(key: KeyCode) -> Self {
+        Self {
+            key,
+            ctrl: false,
+            alt: false,
+            shift: false,
+            logo: false,
+        }
+    }
+
+    pub fn ctrl(self) -> Self {
+        Self { ctrl: true, ..self }
+    }
+
+    pub fn alt(self) -> Self {
+        Self { alt: true, ..self }
+    }
+
+    pub fn shift(self) -> Self {
+        Self { shift: true, ..self }
+    }
+
+    pub fn logo(self) -> Self {
+        Self { logo: true, ..self }
+    }
+
+    pub(crate) fn pressed(key: KeyCode, modifiers: Modifiers) -> Self {
+        Self {
+            key,
+            ctrl: modifiers.ctrl,
+            alt: modifiers.alt,
+            shift: modifiers.shift,
+            logo: modifiers.logo,
+        }
+    }
+}
+
+impl<M: Model> ShortcutMap<M> {
+    pub fn new() -> Self {
+        Self { shortcuts: Vec::new() }
+    }
+
+    pub fn bind(&mut self, shortcut: Shortcut, message: M::Message) -> &mut Self
+    where
+        M::Message: Clone + Send + Sync,
+    {
+        self.shortcuts.push((shortcut, Box::new(move || message.clone())));
+        self
+    }
+
+    pub fn with(mut self, shortcut: Shortcut, message: M::Message) -> Self
+    where
+        M::Message: Clone + Send + Sync,
+    {
+        self.bind(shortcut, message);
+        self
+    }
+
+    pub(crate) fn messages(&self, shortcut: Shortcut) -> impl Iterator<Item = M::Message> + '_ {
+        self.shortcuts
+            .iter()
+            .filter(move |(s, _)| *s == shortcut)
+            .map(|(_, message)| message())
+    }
+}
+
+impl<M: Model> Default for ShortcutMap<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::input::keyboard::KeyboardInput;
+    use bevy::input::ElementState;
+
+    use super::*;
+    use crate::test_util::*;
+    use crate::Ui;
+
+    const CTRL: Modifiers = Modifiers {
+        ctrl: true,
+        alt: false,
+        shift: false,
+        logo: false,
+    };
+
+    #[test]
+    fn messages_of_pressed_shortcut() {
+        let shortcuts = ShortcutMap::<Counter>::new().with(Shortcut::new(KeyCode::Z).ctrl(), Message::Down);
+        let messages = |key, modifiers| {
+            shortcuts
+                .messages(Shortcut::pressed(key, modifiers))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(messages(KeyCode::Z, CTRL), vec![Message::Down]);
+        assert_eq!(messages(KeyCode::Z, Modifiers { ctrl: false, ..CTRL }), vec![]);
+        assert_eq!(messages(KeyCode::Z, Modifiers { shift: true, ..CTRL }), vec![]);
+        assert_eq!(messages(KeyCode::Y, CTRL), vec![]);
+    }
+
+    #[test]
+    fn shortcut_reaches_model() {
+        let mut app = window_app();
+        app.world
+            .insert_resource(ShortcutMap::<Counter>::new().with(Shortcut::new(KeyCode::Z).ctrl(), Message::Down));
+        let entity = spawn_counter(&mut app.world);
+
+        for &key_code in [KeyCode::LControl, KeyCode::Z].iter() {
+            send(
+                &mut app,
+                KeyboardInput {
+                    scan_code: 0,
+                    key_code: Some(key_code),
+                    state: ElementState::Pressed,
+                },
+            );
+        }
+        app.update();
+
+        assert_eq!(app.world.get::<Ui<Counter>>(entity).unwrap().model().value, -1);
+    }
+}
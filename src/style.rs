@@ -1,25 +1,66 @@
-use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 use anyhow::*;
-use bevy::asset::{AssetIoError, AssetLoader, LoadContext, LoadedAsset};
+use bevy::asset::{AssetIoError, AssetLoader, Handle, LoadContext, LoadedAsset};
 use bevy::reflect::TypeUuid;
-use bevy::render::renderer::TextureId;
+use bevy::tasks::CountdownEvent;
+use bevy::utils::HashMap;
 use pixel_widgets::loader::Loader;
 
+/// A pixel_widgets stylesheet loaded from a `.pwss` file, along with the fonts and images it refers to.
+///
+/// Stylesheets are hot reloaded when the asset server watches for changes: the file is loaded again and
+/// every ui using the handle switches to the new style on its next update. Only changes to the `.pwss` file itself
+/// trigger a reload: changes to the fonts and images it refers to are not hot reloaded. They are read along with the
+/// stylesheet, but not registered as asset dependencies, because bevy 0.5 would load each of them as a separate
+/// asset and still not reload the stylesheet when one of them changes. Save the `.pwss` file to pick them up.
+///
+/// A stylesheet that fails to load is logged along with the error, and `AssetServer::get_load_state` reports
+/// `LoadState::Failed` for its handle, so apps can fall back to something else.
 #[derive(TypeUuid)]
 #[uuid = "182aa3fa-a529-4096-a26b-9b49dc5577a3"]
 pub struct Stylesheet {
     pub(crate) style: Arc<pixel_widgets::prelude::Style>,
-    pub(crate) textures: HashMap<usize, TextureId>,
+}
+
+/// Replaces the stylesheet of a single ui entity without touching the shared `Stylesheet` asset.
+/// Removing the component reverts the ui back to the asset.
+pub struct StyleOverride {
+    pub(crate) style: Arc<pixel_widgets::prelude::Style>,
+    // textures of the override are kept apart from those of the asset
+    pub(crate) namespace: u64,
+}
+
+impl StyleOverride {
+    pub fn new(style: Arc<pixel_widgets::prelude::Style>) -> Self {
+        static NEXT_NAMESPACE: AtomicU64 = AtomicU64::new(0);
+        Self {
+            style,
+            namespace: NEXT_NAMESPACE.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+/// Where the style that a ui is currently using comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StyleSource {
+    /// The default style of pixel_widgets, used while there is no stylesheet or it's still loading.
+    Default,
+    Stylesheet(Handle<Stylesheet>),
+    Override,
 }
 
 #[derive(Default)]
 pub struct StylesheetLoader;
 
-struct LoadContextLoader<'a>(&'a LoadContext<'a>);
+struct LoadContextLoader<'a> {
+    load_context: &'a LoadContext<'a>,
+    // reads of referenced files by url, each is counted down once it completes
+    reads: Mutex<HashMap<String, Vec<CountdownEvent>>>,
+}
 
 impl<'a> Loader for LoadContextLoader<'a> {
     #[allow(clippy::type_complexity)]
@@ -28,11 +69,33 @@ impl<'a> Loader for LoadContextLoader<'a> {
     type Error = AssetIoError;
 
     fn load(&self, url: impl AsRef<str>) -> Self::Load {
-        Box::pin(self.0.read_asset_bytes(url.as_ref().to_string()))
+        let read = CountdownEvent::new(1);
+        self.reads
+            .lock()
+            .unwrap()
+            .entry(url.as_ref().to_string())
+            .or_default()
+            .push(read.clone());
+
+        let bytes = self.load_context.read_asset_bytes(url.as_ref().to_string());
+        Box::pin(async move {
+            let result = bytes.await;
+            read.decrement();
+            result
+        })
     }
 
-    fn wait(&self, _url: impl AsRef<str>) -> Self::Wait {
-        unimplemented!()
+    fn wait(&self, url: impl AsRef<str>) -> Self::Wait {
+        // this doesn't wait for changes to the file. a load context only lives for a single load, so the future
+        // resolves once the reads of this load have completed, and right away for files that were never read.
+        // referenced files are never reloaded, only the `.pwss` file is when the asset server reloads it.
+        let reads = self.reads.lock().unwrap().get(url.as_ref()).cloned();
+        Box::pin(async move {
+            for read in reads.into_iter().flatten() {
+                read.listen().await;
+            }
+            Ok(())
+        })
     }
 }
 
@@ -43,11 +106,19 @@ impl AssetLoader for StylesheetLoader {
         load_context: &'a mut LoadContext<'_>,
     ) -> Pin<Box<dyn Future<Output = Result<()>> + 'a + Send>> {
         Box::pin(async move {
-            let loader = LoadContextLoader(load_context);
-            let style = pixel_widgets::prelude::Style::load_from_memory(bytes, &loader, 512, 0).await?;
+            let loader = LoadContextLoader {
+                load_context,
+                reads: Default::default(),
+            };
+            let style = match pixel_widgets::prelude::Style::load_from_memory(bytes, &loader, 512, 0).await {
+                Ok(style) => style,
+                Err(error) => {
+                    log::error!("failed to load stylesheet {}: {}", load_context.path().display(), error);
+                    return Err(error.into());
+                }
+            };
             load_context.set_default_asset(LoadedAsset::new(Stylesheet {
                 style: Arc::new(style),
-                textures: Default::default(),
             }));
             Ok(())
         })
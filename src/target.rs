@@ -0,0 +1,109 @@
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+use bevy::render::pass::LoadOp;
+use bevy::render::renderer::TextureId;
+use bevy::render::texture::FilterMode;
+use bevy::window::WindowId;
+
+/// Draws a ui into a texture instead of a window, for example to show it on a screen in the scene.
+/// The texture has to be added with `UiPluginBuilder::texture_target` first, uis with other textures aren't drawn.
+///
+/// Uis in a texture are laid out at the size of the texture. They don't receive the cursor and mouse buttons of
+/// the window, apps can send their own pointer input through `Ui::event` instead. Keyboard input still reaches them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UiRenderTarget(pub Handle<Texture>);
+
+/// The window a ui is shown in, for example to pop out a panel into an OS window of its own.
+/// Uis without this component are shown in the primary window, a `UiRenderTarget` takes precedence over it.
+///
+/// The ui is laid out at the size of its window and receives the cursor moves of that window. Mouse buttons, scrolling
+/// and touches go to the window the cursor moved in last, keyboard input reaches the uis of all windows.
+///
+/// Secondary windows need a ui pass before their uis are drawn. With `UiPlugin::secondary_windows`, every window that
+/// is created after the plugin was added gets one that clears the window to the `ClearColor`, nothing else is drawn
+/// into it. Windows that the app renders itself get a pass on top of the scene with `add_ui_window_pass`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UiWindow(pub WindowId);
+
+/// A texture that uis can be drawn into, see `UiRenderTarget`.
+///
+/// The texture is created by the ui pass of the target and set as the render resource of `texture`, so materials
+/// can sample it like any other texture. Don't add a `Texture` asset for the handle, it would replace the target.
+/// The pass runs before the main pass, so the scene always shows the ui of the current frame.
+/// Like the window, it's multisampled with the `Msaa` sample count and then resolved into the texture.
+///
+/// Unlike the window, which already shows the scene, a texture target is cleared to transparent by default.
+/// The alpha of what the uis draw on top is blended with `UiBlend::STRAIGHT_ALPHA`, so partly transparent
+/// widgets stay partly transparent in the texture.
+#[derive(Debug, Clone)]
+pub struct UiTextureTarget {
+    pub texture: Handle<Texture>,
+    /// Size of the texture in pixels.
+    pub width: u32,
+    pub height: u32,
+    /// How the texture is filtered when materials sample it, like when it's shown larger than its size.
+    /// This is independent of `UiRenderSettings::filter`, which is about the textures of the uis themselves.
+    /// Use `FilterMode::Nearest` to scale up pixel art uis without blurring them.
+    pub filter: FilterMode,
+    /// What the texture starts out with every frame, before the uis are drawn into it.
+    pub load: LoadOp<Color>,
+}
+
+impl UiTextureTarget {
+    pub fn new(texture: Handle<Texture>, width: u32, height: u32) -> Self {
+        Self {
+            texture,
+            width,
+            height,
+            filter: FilterMode::Linear,
+            load: LoadOp::Clear(Color::NONE),
+        }
+    }
+
+    pub fn with_filter(mut self, filter: FilterMode) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Clears the texture to an opaque background with `LoadOp::Clear`, or keeps the previous frame with
+    /// `LoadOp::Load`, for example to draw a ui on top of what another pass rendered into the texture.
+    pub fn with_load(mut self, load: LoadOp<Color>) -> Self {
+        self.load = load;
+        self
+    }
+}
+
+/// The texture targets that were added to the `UiPlugin`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UiTextureTargets(pub(crate) Vec<UiTextureTarget>);
+
+impl UiTextureTargets {
+    pub(crate) fn get(&self, texture: &Handle<Texture>) -> Option<&UiTextureTarget> {
+        self.0.iter().find(|target| &target.texture == texture)
+    }
+}
+
+/// What the pass of a `UiNode` draws into.
+#[derive(Debug, Clone)]
+pub(crate) enum PassTarget {
+    Window(WindowId),
+    Texture(UiTextureTarget),
+}
+
+impl Default for PassTarget {
+    fn default() -> Self {
+        Self::Window(WindowId::primary())
+    }
+}
+
+/// Textures of a texture target, created by `render_ui` and rendered to by the `UiNode` of the target.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TargetTextures {
+    pub(crate) color: TextureId,
+    // the multisampled texture that is resolved into `color`, with msaa
+    pub(crate) sampled: Option<TextureId>,
+    pub(crate) depth: Option<TextureId>,
+}
+
+pub(crate) type SharedTargetTextures = Arc<Mutex<Option<TargetTextures>>>;
@@ -0,0 +1,306 @@
+//! Models, styles and apps shared by the tests of the different modules.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use bevy::app::{App, Events};
+use bevy::asset::{AssetPlugin, Assets, Handle, HandleId, HandleUntyped};
+use bevy::core::CorePlugin;
+use bevy::ecs::component::Component;
+use bevy::ecs::prelude::*;
+use bevy::input::mouse::{MouseButton, MouseButtonInput};
+use bevy::input::{ElementState, InputPlugin};
+use bevy::math::Vec2;
+use bevy::reflect::TypeUuid;
+use bevy::render::texture::Texture;
+use bevy::window::{
+    CursorMoved, Window, WindowCreated, WindowDescriptor, WindowId, WindowPlugin, WindowResized, Windows,
+};
+use futures_lite::future::block_on;
+use pixel_widgets::prelude::*;
+use pixel_widgets::{widget, Command, Model, UpdateModel};
+
+use crate::plugin::UiPlugin;
+use crate::style::{StyleOverride, Stylesheet};
+use crate::update::UpdateUiSystemParams;
+use crate::{DisabledLoader, Ui, UiDraw};
+
+/// Two buttons stacked on top of each other, "up" at `UP` and "down" right below it.
+/// The buttons have a fixed size and no text, so they don't depend on fonts.
+#[derive(Default)]
+pub struct Counter {
+    pub value: i32,
+    pub state: ManagedState<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Up,
+    Down,
+}
+
+/// The center of the "up" button, in ui coordinates.
+pub const UP: (f32, f32) = (50.0, 20.0);
+
+pub const STYLE: &str = "button { width: 100; height: 40; background: #f00; }";
+
+impl Model for Counter {
+    type Message = Message;
+
+    fn view(&mut self) -> widget::Node<Message> {
+        let mut state = self.state.tracker();
+        widget::Column::new()
+            .push(widget::Button::new(state.get("up"), widget::Column::new()).on_clicked(Message::Up))
+            .push(widget::Button::new(state.get("down"), widget::Column::new()).on_clicked(Message::Down))
+            .into_node()
+    }
+}
+
+impl<'a> UpdateModel<'a> for Counter {
+    type State = ();
+
+    fn update(&mut self, message: Message, _: &mut ()) -> Vec<Command<Message>> {
+        match message {
+            Message::Up => self.value += 1,
+            Message::Down => self.value -= 1,
+        }
+        Vec::new()
+    }
+}
+
+/// A button, and another one for each of `shared` and `local` that is set. `shared` can be changed from outside
+/// of the ui, which pixel_widgets can't observe.
+#[derive(Default)]
+pub struct Toggles {
+    pub shared: Arc<AtomicBool>,
+    pub local: bool,
+    pub state: ManagedState<String>,
+}
+
+impl Model for Toggles {
+    type Message = Message;
+
+    fn view(&mut self) -> widget::Node<Message> {
+        let mut state = self.state.tracker();
+        let mut column = widget::Column::new().push(widget::Button::new(state.get("base"), widget::Column::new()));
+        if self.shared.load(Ordering::Relaxed) {
+            column = column.push(widget::Button::new(state.get("shared"), widget::Column::new()));
+        }
+        if self.local {
+            column = column.push(widget::Button::new(state.get("local"), widget::Column::new()));
+        }
+        column.into_node()
+    }
+}
+
+impl<'a> UpdateModel<'a> for Toggles {
+    type State = ();
+
+    fn update(&mut self, _: Message, _: &mut ()) -> Vec<Command<Message>> {
+        Vec::new()
+    }
+}
+
+pub fn style() -> Arc<Style> {
+    let style = block_on(Style::load_from_memory(STYLE.as_bytes(), &DisabledLoader, 512, 0));
+    Arc::new(style.unwrap())
+}
+
+pub fn update_counter(params: UpdateUiSystemParams<Counter>) {
+    params.update(());
+}
+
+pub fn update_toggles(params: UpdateUiSystemParams<Toggles>) {
+    params.update(());
+}
+
+/// Spawns a `Counter` ui that uses `STYLE`, so it doesn't wait for a stylesheet asset.
+pub fn spawn_counter(world: &mut World) -> Entity {
+    world
+        .spawn()
+        .insert_bundle((
+            Ui::new(Counter::default()),
+            UiDraw::default(),
+            StyleOverride::new(style()),
+        ))
+        .id()
+}
+
+/// Spawns a `Toggles` ui that uses `STYLE`.
+pub fn spawn_toggles(world: &mut World, toggles: Toggles) -> Entity {
+    world
+        .spawn()
+        .insert_bundle((Ui::new(toggles), UiDraw::default(), StyleOverride::new(style())))
+        .id()
+}
+
+/// Spawns a `Counter` ui that uses the stylesheet asset `stylesheet`, which doesn't have to be loaded yet.
+pub fn spawn_counter_with_stylesheet(world: &mut World, stylesheet: Handle<Stylesheet>) -> Entity {
+    world
+        .spawn()
+        .insert_bundle((Ui::new(Counter::default()), UiDraw::default(), stylesheet))
+        .id()
+}
+
+/// A new stylesheet asset that isn't loaded until `load_stylesheet`.
+pub fn stylesheet_handle() -> Handle<Stylesheet> {
+    Handle::weak(HandleId::random::<Stylesheet>())
+}
+
+/// Sets the stylesheet asset of `handle` to `style`, like the asset server does when it's done loading.
+pub fn load_stylesheet(app: &mut App, handle: &Handle<Stylesheet>, style: Arc<Style>) {
+    app.world
+        .get_resource_mut::<Assets<Stylesheet>>()
+        .unwrap()
+        .set_untracked(handle.id, Stylesheet { style });
+}
+
+/// A texture for uis to be drawn into, see `UiTextureTarget`.
+pub fn target_texture() -> Handle<Texture> {
+    HandleUntyped::weak_from_u64(Texture::TYPE_UUID, 4150936264386209013).typed()
+}
+
+/// An app that updates `Counter` and `Toggles` uis, without a window or renderer.
+pub fn headless_app() -> App {
+    let mut app = App::build();
+    app.add_plugin(CorePlugin)
+        .add_plugin(AssetPlugin)
+        .add_plugin(InputPlugin)
+        .add_plugin(WindowPlugin {
+            add_primary_window: false,
+            exit_on_close: false,
+        })
+        .add_plugin(UiPlugin::default())
+        .add_system(update_counter.system())
+        .add_system(update_toggles.system());
+    app.app
+}
+
+/// Like `headless_app`, but with a primary window of 800x600 pixels.
+pub fn window_app() -> App {
+    let mut app = headless_app();
+    add_window(&mut app, 800, 600);
+    app
+}
+
+/// An app that renders `Counter` uis with `plugin` into a primary window of 800x600 pixels.
+/// Its render resource context records what the ui systems create and remove, see `take_calls`.
+pub fn render_app(plugin: UiPlugin) -> App {
+    render_app_with_msaa(plugin, bevy::render::render_graph::base::Msaa::default())
+}
+
+/// Like `render_app`, but with multisampling set up before the render graph is built.
+pub fn render_app_with_msaa(plugin: UiPlugin, msaa: bevy::render::render_graph::base::Msaa) -> App {
+    use bevy::diagnostic::DiagnosticsPlugin;
+    use bevy::render::RenderPlugin;
+    use bevy::transform::TransformPlugin;
+
+    let mut app = App::build();
+    app.insert_resource(msaa)
+        .add_plugin(CorePlugin)
+        .add_plugin(TransformPlugin)
+        .add_plugin(DiagnosticsPlugin)
+        .add_plugin(InputPlugin)
+        .add_plugin(WindowPlugin {
+            add_primary_window: false,
+            exit_on_close: false,
+        })
+        .add_plugin(AssetPlugin)
+        .add_plugin(RenderPlugin::default())
+        .add_plugin(plugin)
+        .add_system(update_counter.system());
+    let mut app = app.app;
+    insert_recording_context(&mut app);
+    add_window(&mut app, 800, 600);
+    app
+}
+
+/// Lets the ui systems draw without a gpu, recording what they create and remove.
+pub fn insert_recording_context(app: &mut App) {
+    use bevy::render::renderer::RenderResourceContext;
+
+    use crate::testing::RecordingRenderResourceContext;
+
+    app.world
+        .insert_resource::<Box<dyn RenderResourceContext>>(Box::new(RecordingRenderResourceContext::default()));
+}
+
+/// Takes the calls recorded by the `RecordingRenderResourceContext` of the app.
+pub fn take_calls(app: &App) -> Vec<crate::testing::ResourceCall> {
+    use bevy::render::renderer::RenderResourceContext;
+
+    use crate::testing::RecordingRenderResourceContext;
+
+    let context = app.world.get_resource::<Box<dyn RenderResourceContext>>().unwrap();
+    (**context)
+        .downcast_ref::<RecordingRenderResourceContext>()
+        .unwrap()
+        .take_calls()
+}
+
+pub fn add_window(app: &mut App, width: u32, height: u32) {
+    add_window_with(app, &WindowDescriptor::default(), width, height);
+}
+
+/// Like `add_window`, but with the settings of `descriptor`, like its resize constraints.
+pub fn add_window_with(app: &mut App, descriptor: &WindowDescriptor, width: u32, height: u32) {
+    let window = Window::new(WindowId::primary(), descriptor, width, height, 1.0, None);
+    app.world.get_resource_mut::<Windows>().unwrap().add(window);
+}
+
+/// Adds another window and lets the app know it was created, like winit would.
+pub fn add_secondary_window(app: &mut App, width: u32, height: u32) -> WindowId {
+    let id = WindowId::new();
+    let window = Window::new(id, &WindowDescriptor::default(), width, height, 1.0, None);
+    app.world.get_resource_mut::<Windows>().unwrap().add(window);
+    send(app, WindowCreated { id });
+    id
+}
+
+/// Resizes the primary window like winit would.
+pub fn resize_window(app: &mut App, width: u32, height: u32) {
+    app.world
+        .get_resource_mut::<Windows>()
+        .unwrap()
+        .get_primary_mut()
+        .unwrap()
+        .update_actual_size_from_backend(width, height);
+    send(
+        app,
+        WindowResized {
+            id: WindowId::primary(),
+            width: width as f32,
+            height: height as f32,
+        },
+    );
+}
+
+pub fn send<T: Component>(app: &mut App, event: T) {
+    app.world.get_resource_mut::<Events<T>>().unwrap().send(event);
+}
+
+/// Clicks the "up" button of the `Counter` uis, for uis that are `height` logical pixels high.
+pub fn click_up(app: &mut App, height: f32) {
+    click_up_in_window(app, WindowId::primary(), height);
+}
+
+/// Like `click_up`, but in the window `id`.
+pub fn click_up_in_window(app: &mut App, id: WindowId, height: f32) {
+    // bevy's cursor positions start at the bottom
+    send(
+        app,
+        CursorMoved {
+            id,
+            position: Vec2::new(UP.0, height - UP.1),
+        },
+    );
+    for &state in [ElementState::Pressed, ElementState::Released].iter() {
+        send(
+            app,
+            MouseButtonInput {
+                button: MouseButton::Left,
+                state,
+            },
+        );
+    }
+}
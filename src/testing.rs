@@ -0,0 +1,267 @@
+use std::ops::Range;
+use std::sync::Mutex;
+
+use bevy::asset::{Assets, Handle, HandleUntyped};
+use bevy::render::pipeline::{BindGroupDescriptorId, PipelineDescriptor};
+use bevy::render::renderer::*;
+use bevy::render::shader::{Shader, ShaderError};
+use bevy::render::texture::{FilterMode, SamplerDescriptor, TextureDescriptor};
+use bevy::window::Window;
+
+/// A resource lifetime call made on a `RecordingRenderResourceContext`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResourceCall {
+    CreateBuffer { buffer: BufferId, info: BufferInfo, data: Option<Vec<u8>> },
+    RemoveBuffer(BufferId),
+    CreateTexture { texture: TextureId, descriptor: TextureDescriptor },
+    RemoveTexture(TextureId),
+    /// Samplers are recorded with their filters only, the whole descriptor can't be compared.
+    CreateSampler {
+        sampler: SamplerId,
+        mag_filter: FilterMode,
+        min_filter: FilterMode,
+        mipmap_filter: FilterMode,
+    },
+    RemoveSampler(SamplerId),
+    CreateBindGroup(BindGroup),
+}
+
+/// A `RenderResourceContext` that doesn't need a gpu and records which buffers, textures, samplers and bind groups
+/// are created and removed, so the resource management of the ui systems can be checked without a window.
+/// Everything else is forwarded to bevy's `HeadlessRenderResourceContext`.
+#[derive(Debug, Default)]
+pub struct RecordingRenderResourceContext {
+    inner: HeadlessRenderResourceContext,
+    calls: Mutex<Vec<ResourceCall>>,
+}
+
+impl RecordingRenderResourceContext {
+    /// Takes the calls that were recorded so far.
+    pub fn take_calls(&self) -> Vec<ResourceCall> {
+        std::mem::take(&mut *self.calls.lock().unwrap())
+    }
+
+    fn record(&self, call: ResourceCall) {
+        self.calls.lock().unwrap().push(call);
+    }
+}
+
+impl RenderResourceContext for RecordingRenderResourceContext {
+    fn create_swap_chain(&self, window: &Window) {
+        self.inner.create_swap_chain(window)
+    }
+
+    fn next_swap_chain_texture(&self, window: &Window) -> TextureId {
+        self.inner.next_swap_chain_texture(window)
+    }
+
+    fn drop_swap_chain_texture(&self, resource: TextureId) {
+        self.inner.drop_swap_chain_texture(resource)
+    }
+
+    fn drop_all_swap_chain_textures(&self) {
+        self.inner.drop_all_swap_chain_textures()
+    }
+
+    fn create_sampler(&self, sampler_descriptor: &SamplerDescriptor) -> SamplerId {
+        let sampler = self.inner.create_sampler(sampler_descriptor);
+        self.record(ResourceCall::CreateSampler {
+            sampler,
+            mag_filter: sampler_descriptor.mag_filter,
+            min_filter: sampler_descriptor.min_filter,
+            mipmap_filter: sampler_descriptor.mipmap_filter,
+        });
+        sampler
+    }
+
+    fn create_texture(&self, texture_descriptor: TextureDescriptor) -> TextureId {
+        let texture = self.inner.create_texture(texture_descriptor);
+        self.record(ResourceCall::CreateTexture {
+            texture,
+            descriptor: texture_descriptor,
+        });
+        texture
+    }
+
+    fn create_buffer(&self, buffer_info: BufferInfo) -> BufferId {
+        let buffer = self.inner.create_buffer(buffer_info.clone());
+        self.record(ResourceCall::CreateBuffer {
+            buffer,
+            info: buffer_info,
+            data: None,
+        });
+        buffer
+    }
+
+    fn write_mapped_buffer(
+        &self,
+        id: BufferId,
+        range: Range<u64>,
+        write: &mut dyn FnMut(&mut [u8], &dyn RenderResourceContext),
+    ) {
+        self.inner.write_mapped_buffer(id, range, write)
+    }
+
+    fn read_mapped_buffer(&self, id: BufferId, range: Range<u64>, read: &dyn Fn(&[u8], &dyn RenderResourceContext)) {
+        self.inner.read_mapped_buffer(id, range, read)
+    }
+
+    fn map_buffer(&self, id: BufferId, mode: BufferMapMode) {
+        self.inner.map_buffer(id, mode)
+    }
+
+    fn unmap_buffer(&self, id: BufferId) {
+        self.inner.unmap_buffer(id)
+    }
+
+    fn create_buffer_with_data(&self, buffer_info: BufferInfo, data: &[u8]) -> BufferId {
+        let buffer = self.inner.create_buffer_with_data(buffer_info.clone(), data);
+        self.record(ResourceCall::CreateBuffer {
+            buffer,
+            info: buffer_info,
+            data: Some(data.to_vec()),
+        });
+        buffer
+    }
+
+    fn create_shader_module(&self, shader_handle: &Handle<Shader>, shaders: &Assets<Shader>) {
+        self.inner.create_shader_module(shader_handle, shaders)
+    }
+
+    fn create_shader_module_from_source(&self, shader_handle: &Handle<Shader>, shader: &Shader) {
+        self.inner.create_shader_module_from_source(shader_handle, shader)
+    }
+
+    fn get_specialized_shader(&self, shader: &Shader, macros: Option<&[String]>) -> Result<Shader, ShaderError> {
+        // the headless context returns glsl as is, but the pipeline compiler can only reflect spir-v
+        shader.get_spirv_shader(macros)
+    }
+
+    fn remove_buffer(&self, buffer: BufferId) {
+        self.inner.remove_buffer(buffer);
+        self.record(ResourceCall::RemoveBuffer(buffer));
+    }
+
+    fn remove_texture(&self, texture: TextureId) {
+        self.inner.remove_texture(texture);
+        self.record(ResourceCall::RemoveTexture(texture));
+    }
+
+    fn remove_sampler(&self, sampler: SamplerId) {
+        self.inner.remove_sampler(sampler);
+        self.record(ResourceCall::RemoveSampler(sampler));
+    }
+
+    fn get_buffer_info(&self, buffer: BufferId) -> Option<BufferInfo> {
+        self.inner.get_buffer_info(buffer)
+    }
+
+    fn get_aligned_uniform_size(&self, size: usize, dynamic: bool) -> usize {
+        self.inner.get_aligned_uniform_size(size, dynamic)
+    }
+
+    fn get_aligned_texture_size(&self, data_size: usize) -> usize {
+        self.inner.get_aligned_texture_size(data_size)
+    }
+
+    fn set_asset_resource_untyped(&self, handle: HandleUntyped, resource: RenderResourceId, index: u64) {
+        self.inner.set_asset_resource_untyped(handle, resource, index)
+    }
+
+    fn get_asset_resource_untyped(&self, handle: HandleUntyped, index: u64) -> Option<RenderResourceId> {
+        self.inner.get_asset_resource_untyped(handle, index)
+    }
+
+    fn remove_asset_resource_untyped(&self, handle: HandleUntyped, index: u64) {
+        self.inner.remove_asset_resource_untyped(handle, index)
+    }
+
+    fn create_render_pipeline(
+        &self,
+        pipeline_handle: Handle<PipelineDescriptor>,
+        pipeline_descriptor: &PipelineDescriptor,
+        shaders: &Assets<Shader>,
+    ) {
+        self.inner
+            .create_render_pipeline(pipeline_handle, pipeline_descriptor, shaders)
+    }
+
+    fn bind_group_descriptor_exists(&self, bind_group_descriptor_id: BindGroupDescriptorId) -> bool {
+        self.inner.bind_group_descriptor_exists(bind_group_descriptor_id)
+    }
+
+    fn create_bind_group(&self, bind_group_descriptor_id: BindGroupDescriptorId, bind_group: &BindGroup) {
+        self.inner.create_bind_group(bind_group_descriptor_id, bind_group);
+        self.record(ResourceCall::CreateBindGroup(bind_group.clone()));
+    }
+
+    fn clear_bind_groups(&self) {
+        self.inner.clear_bind_groups()
+    }
+
+    fn remove_stale_bind_groups(&self) {
+        self.inner.remove_stale_bind_groups()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::renderer::{BufferId, BufferUsage};
+    use pixel_widgets::draw::Vertex;
+
+    use super::ResourceCall;
+    use crate::test_util::*;
+
+    fn created_vertex_buffers(calls: &[ResourceCall]) -> Vec<BufferId> {
+        calls
+            .iter()
+            .filter_map(|call| match call {
+                ResourceCall::CreateBuffer { buffer, info, .. } if info.buffer_usage == BufferUsage::VERTEX => {
+                    Some(*buffer)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn redraw_after_resize_removes_old_vertex_buffer() {
+        let mut app = window_app();
+        insert_recording_context(&mut app);
+        spawn_counter(&mut app.world);
+
+        app.update();
+        let old_vertex_buffer = created_vertex_buffers(&take_calls(&app))[0];
+
+        resize_window(&mut app, 640, 480);
+        app.update();
+        let calls = take_calls(&app);
+        assert_eq!(created_vertex_buffers(&calls).len(), 1);
+        assert!(calls.contains(&ResourceCall::RemoveBuffer(old_vertex_buffer)));
+    }
+
+    #[test]
+    fn first_frame_is_laid_out_at_the_window_size() {
+        let mut app = window_app();
+        insert_recording_context(&mut app);
+        spawn_counter(&mut app.world);
+        // no WindowResized is sent, the ui gets the size of the window on its first update
+        app.update();
+
+        let vertices = take_calls(&app)
+            .into_iter()
+            .find_map(|call| match call {
+                ResourceCall::CreateBuffer {
+                    info, data: Some(data), ..
+                } if info.buffer_usage == BufferUsage::VERTEX => Some(data),
+                _ => None,
+            })
+            .unwrap();
+        // the buttons are 100 pixels wide, an eighth of the window
+        let right = vertices
+            .chunks(std::mem::size_of::<Vertex>())
+            .map(|vertex| f32::from_ne_bytes([vertex[0], vertex[1], vertex[2], vertex[3]]))
+            .fold(f32::MIN, f32::max);
+        assert!((right - -0.75).abs() < 1e-3, "{}", right);
+    }
+}
@@ -3,6 +3,17 @@
 layout(set = 0, binding = 0) uniform texture2D t_Color;
 layout(set = 0, binding = 1) uniform sampler s_Color;
 
+layout(set = 1, binding = 0) uniform UiUniforms {
+    mat4 ColorFilter;
+    float Gamma;
+    float FlipY;
+};
+
+layout(set = 2, binding = 0) uniform UiClip {
+    vec4 ClipRect;
+    float ClipFeather;
+};
+
 layout(location = 0) in vec2 v_Uv;
 layout(location = 1) in vec4 v_Color;
 layout(location = 2) in float v_Mode;
@@ -15,5 +26,10 @@ void main() {
     color.y = mix(color.y, 1.0, v_Mode);
     color.z = mix(color.z, 1.0, v_Mode);
     color.w = mix(color.w, 1.0, v_Mode);
-    Target0 = v_Color * color;
+    vec4 filtered = ColorFilter * (v_Color * color);
+    // fade out towards the edges of the clip rect, the scissor rect cuts off everything outside of it
+    vec2 inside = min(gl_FragCoord.xy - ClipRect.xy, ClipRect.zw - gl_FragCoord.xy);
+    float coverage = ClipFeather > 0.0 ? clamp(min(inside.x, inside.y) / ClipFeather, 0.0, 1.0) : 1.0;
+    // pow is undefined for negative values, which color filters with negative coefficients can produce
+    Target0 = vec4(pow(max(filtered.rgb, vec3(0.0)), vec3(1.0 / Gamma)), filtered.a * coverage);
 }
\ No newline at end of file
@@ -5,6 +5,12 @@ layout(location = 1) in vec2 Vertex_Uv;
 layout(location = 2) in vec4 Vertex_Color;
 layout(location = 3) in float Vertex_Mode;
 
+layout(set = 1, binding = 0) uniform UiUniforms {
+    mat4 ColorFilter;
+    float Gamma;
+    float FlipY;
+};
+
 layout(location = 0) out vec2 v_Uv;
 layout(location = 1) out vec4 v_Color;
 layout(location = 2) out float v_Mode;
@@ -13,5 +19,6 @@ void main() {
     v_Uv = Vertex_Uv;
     v_Color = Vertex_Color;
     v_Mode = Vertex_Mode;
-    gl_Position = vec4(Vertex_Position.x, -Vertex_Position.y, 0.0, 1.0);
+    float y = mix(-Vertex_Position.y, Vertex_Position.y, FlipY);
+    gl_Position = vec4(Vertex_Position.x, y, 0.0, 1.0);
 }
\ No newline at end of file
@@ -1,21 +1,153 @@
+use std::sync::Arc;
+
+use bevy::ecs::entity::Entities;
 use bevy::ecs::system::SystemParam;
 use bevy::input::keyboard::KeyboardInput;
 use bevy::input::mouse::{MouseButtonInput, MouseWheel};
+use bevy::input::touch::{ForceTouch, TouchInput, TouchPhase};
 use bevy::input::prelude::*;
 use bevy::input::ElementState;
 use bevy::prelude::*;
 use bevy::render::renderer::{BufferInfo, BufferUsage, RenderResourceContext};
-use bevy::window::WindowResized;
+use bevy::window::{WindowId, WindowResized};
 use pixel_widgets::draw::{DrawList, Vertex};
 use pixel_widgets::event::{Event, Key, Modifiers};
 use pixel_widgets::prelude::*;
+use pixel_widgets::Command;
 use zerocopy::AsBytes;
 
-use crate::style::Stylesheet;
+use crate::modal::ModalStack;
+use crate::pixel_widgets_node::{ReleasedBuffers, UiRenderSettings};
+use crate::shortcut::{Shortcut, ShortcutMap};
+use crate::style::{StyleOverride, StyleSource, Stylesheet};
+use crate::target::{UiRenderTarget, UiTextureTargets, UiWindow};
 use crate::{Ui, UiDraw};
 
 pub struct State {
     modifiers: Modifiers,
+    // the touch that acts as the mouse, other touches are ignored
+    touch: Option<u64>,
+    // the normalized force of that touch, if the device reports it
+    pressure: Option<f32>,
+    // the window the cursor moved in last, which receives the mouse buttons
+    pointer_window: WindowId,
+}
+
+/// Settings that control how bevy input is translated to pixel_widgets events.
+#[derive(Debug, Clone)]
+pub struct UiInputSettings {
+    /// Scroll horizontally when the vertical wheel is used while shift is held.
+    /// Some platforms already do this by themselves, so it's disabled by default.
+    pub shift_scrolls_horizontally: bool,
+    /// Distance in logical pixels the cursor has to move while pressed before it counts as a drag.
+    pub drag_threshold: f32,
+    /// Origin of the cursor coordinates that are sent to the ui.
+    pub cursor_origin: CoordinateOrigin,
+    /// Send cursor coordinates in physical pixels instead of logical pixels.
+    /// pixel_widgets lays out in the same units as the cursor, while the ui is still resized to the logical
+    /// window size, so this is only useful for custom render targets that handle the scaling themselves.
+    pub physical_cursor: bool,
+    /// Characters that the ui's font can render. Text input of other characters is replaced by
+    /// `fallback_character`, or dropped if there is none. All characters are let through if not set.
+    pub supported_characters: Option<fn(char) -> bool>,
+    pub fallback_character: Option<char>,
+    /// Only send the last cursor position of a frame, instead of every intermediate position.
+    pub coalesce_cursor_moves: bool,
+}
+
+/// Corner of the window that cursor coordinates are relative to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoordinateOrigin {
+    /// Y grows downwards, this is what pixel_widgets expects.
+    TopLeft,
+    /// Y grows upwards, like the cursor positions reported by bevy.
+    BottomLeft,
+}
+
+/// What the left mouse button has been doing, as seen by a `Ui`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointerGesture {
+    Idle,
+    /// Pressed, but not moved beyond the drag threshold yet.
+    Pressed { origin: (f32, f32) },
+    Dragging { origin: (f32, f32) },
+    /// Released without moving beyond the drag threshold.
+    Clicked { position: (f32, f32) },
+    /// Released after dragging.
+    Dropped { origin: (f32, f32), position: (f32, f32) },
+}
+
+pub(crate) struct InputTracker {
+    cursor: (f32, f32),
+    gesture: PointerGesture,
+    pressed: Vec<Key>,
+    // releases that still have to be sent to the ui
+    released: Vec<Key>,
+    // whether the modifiers have to be released along with them
+    release_modifiers: bool,
+    pressure: Option<f32>,
+}
+
+impl Default for UiInputSettings {
+    fn default() -> Self {
+        Self {
+            shift_scrolls_horizontally: false,
+            drag_threshold: 4.0,
+            cursor_origin: CoordinateOrigin::TopLeft,
+            physical_cursor: false,
+            supported_characters: None,
+            fallback_character: None,
+            coalesce_cursor_moves: true,
+        }
+    }
+}
+
+impl Default for InputTracker {
+    fn default() -> Self {
+        Self {
+            cursor: (0.0, 0.0),
+            gesture: PointerGesture::Idle,
+            pressed: Vec::new(),
+            released: Vec::new(),
+            release_modifiers: false,
+            pressure: None,
+        }
+    }
+}
+
+impl InputTracker {
+    fn track(&mut self, event: Event, settings: &UiInputSettings) {
+        match event {
+            Event::Press(key) if !self.pressed.contains(&key) => self.pressed.push(key),
+            Event::Release(key) => self.pressed.retain(|&pressed| pressed != key),
+            _ => (),
+        }
+
+        match event {
+            Event::Cursor(x, y) => {
+                self.cursor = (x, y);
+                if let PointerGesture::Pressed { origin } = self.gesture {
+                    let (dx, dy) = (x - origin.0, y - origin.1);
+                    if dx * dx + dy * dy > settings.drag_threshold * settings.drag_threshold {
+                        self.gesture = PointerGesture::Dragging { origin };
+                    }
+                }
+            }
+            Event::Press(Key::LeftMouseButton) => {
+                self.gesture = PointerGesture::Pressed { origin: self.cursor };
+            }
+            Event::Release(Key::LeftMouseButton) => {
+                self.gesture = match self.gesture {
+                    PointerGesture::Dragging { origin } => PointerGesture::Dropped {
+                        origin,
+                        position: self.cursor,
+                    },
+                    _ => PointerGesture::Clicked { position: self.cursor },
+                };
+            }
+            _ => (),
+        }
+    }
 }
 
 impl Default for State {
@@ -27,6 +159,9 @@ impl Default for State {
                 shift: false,
                 logo: false,
             },
+            touch: None,
+            pressure: None,
+            pointer_window: WindowId::primary(),
         }
     }
 }
@@ -40,61 +175,144 @@ impl<M: Model + Send + Sync> Ui<M> {
             self.ui.command(cmd, resources);
         }
     }
+
+    /// Drops all commands that were queued but not processed yet, so stale results don't reach a model that was
+    /// just swapped in. Futures that are still in flight aren't cancelled, their results are delivered to the
+    /// swapped-in model once they complete. Returns the number of discarded commands.
+    pub fn discard_pending_commands(&mut self) -> usize {
+        self.receiver.get_mut().unwrap().try_iter().count()
+    }
+
+    /// The current or most recent gesture of the left mouse button.
+    pub fn gesture(&self) -> PointerGesture {
+        self.input.gesture
+    }
+
+    /// The pressure of the touch that acts as the left mouse button, between 0.0 and 1.0.
+    /// `None` while there is no touch, or when the device doesn't report the force of touches.
+    pub fn pressure(&self) -> Option<f32> {
+        self.input.pressure
+    }
+
+    /// Whether the ui has received a press of `key` without a matching release.
+    pub fn is_pressed(&self, key: Key) -> bool {
+        self.input.pressed.contains(&key)
+    }
+
+    /// Releases all keys and buttons that are currently held, for example before a scene transition.
+    /// The release events are sent to the ui on the next update. The modifiers of this ui are released as well,
+    /// they count as held again once they are pressed again. Other uis and shortcuts still see the modifiers that
+    /// are physically held.
+    pub fn release_all_inputs(&mut self) {
+        let input = &mut self.input;
+        input.released.extend(input.pressed.drain(..));
+        input.release_modifiers = true;
+    }
+
+    fn queue_command(&self, command: Command<M::Message>) {
+        if self.sender.sender.try_send(command).is_err() {
+            log::warn!("command queue is full, dropping command");
+        }
+    }
 }
 
+/// The bevy input events that are translated for the uis.
+/// Kept separate from `UpdateUiSystemParams`, because bevy supports at most 16 fields per system param.
 #[derive(SystemParam)]
-pub struct UpdateUiSystemParams<'a, M: Model + Send + Sync> {
-    state: Local<'a, State>,
-    pub windows: Res<'a, Windows>,
+pub struct UiInputEvents<'a> {
     pub keyboard_events: EventReader<'a, KeyboardInput>,
     pub character_events: EventReader<'a, ReceivedCharacter>,
     pub mouse_button_events: EventReader<'a, MouseButtonInput>,
     pub cursor_moved_events: EventReader<'a, CursorMoved>,
     pub mouse_wheel_events: EventReader<'a, MouseWheel>,
+    pub touch_events: EventReader<'a, TouchInput>,
     pub window_resize_events: EventReader<'a, WindowResized>,
+}
+
+#[derive(SystemParam)]
+pub struct UpdateUiSystemParams<'a, M: Model + Send + Sync> {
+    state: Local<'a, State>,
+    /// Without the window of a ui, or without `Windows` at all, uis keep their own size and cursor positions are
+    /// relative to the uis themselves.
+    pub windows: Option<Res<'a, Windows>>,
+    pub input: UiInputEvents<'a>,
     pub stylesheets: Res<'a, Assets<Stylesheet>>,
-    pub render_resource_context: Res<'a, Box<dyn RenderResourceContext>>,
+    pub render_resource_context: Option<Res<'a, Box<dyn RenderResourceContext>>>,
+    pub shortcuts: Option<Res<'a, ShortcutMap<M>>>,
+    pub input_settings: Res<'a, UiInputSettings>,
+    pub render_settings: Res<'a, UiRenderSettings>,
+    pub modals: Res<'a, ModalStack>,
+    pub entities: &'a Entities,
+    released_buffers: Res<'a, ReleasedBuffers>,
+    texture_targets: Res<'a, UiTextureTargets>,
     query: Query<
         'a,
         (
+            Entity,
             &'static mut Ui<M>,
             &'static mut UiDraw,
             Option<&'static Handle<Stylesheet>>,
+            Option<&'static StyleOverride>,
+            Option<&'static UiRenderTarget>,
+            Option<&'static UiWindow>,
         ),
     >,
 }
 
 impl<'a, M: Model + Send + Sync> UpdateUiSystemParams<'a, M> {
+    /// Sends this frame's input to all uis and redraws them if needed.
+    ///
+    /// Bevy keeps a separate queue for every kind of input event and doesn't timestamp them, so the order in which
+    /// they happened within a frame is lost. Events are sent in this order instead: resizes, keys (with their
+    /// modifiers), text, cursor moves, scrolling, mouse buttons and finally touches. Cursor moves always come before
+    /// the clicks of the same frame, so clicks hit the widget under the cursor.
+    ///
+    /// The first finger that touches the screen acts as the left mouse button, until it's lifted again.
+    /// pixel_widgets has no notion of multi-touch, so other fingers are ignored in the meantime.
+    ///
+    /// Uis receive the resizes and cursor moves of their own `UiWindow`. Mouse buttons, scrolling and touches don't
+    /// say which window they happened in, they go to the window the cursor moved in last.
+    ///
+    /// With the `clipboard` feature, ctrl+v (cmd+v on macOS) sends the text on the clipboard in place of the V press,
+    /// see `paste_events`.
     pub fn update<S: 'a>(mut self, mut state: S)
     where
         M: UpdateModel<'a, State = S>,
     {
+        // the render resource context may not exist yet during the first frames, or at all when running headless.
+        // uis are updated without it, they are only drawn once it's there.
+        let render_resource_context = self.render_resource_context.take();
+
+        // headless apps, like tests, may not have a window
+        let windows = self.windows.as_deref();
+        let window_of = |id: WindowId| windows.and_then(|windows| windows.get(id));
+
+        // events with the window they happened in. keyboard input isn't tied to a window and reaches all uis.
         let mut events = Vec::new();
-        let window = self.windows.get_primary().unwrap();
+        let mut shortcuts = Vec::new();
+        // pending redraws are kept until one of the windows has focus again
+        let unfocused = windows.map_or(false, |windows| {
+            windows.iter().next().is_some() && !windows.iter().any(Window::is_focused)
+        });
 
-        for event in self.window_resize_events.iter() {
-            events.push(Event::Resize(event.width as f32, event.height as f32));
+        // every window is resized on its own, only its uis are laid out again
+        for event in self.input.window_resize_events.iter() {
+            let window = match window_of(event.id) {
+                Some(window) => window,
+                None => continue,
+            };
+            if event.width > 0.0 && event.height > 0.0 {
+                let (width, height) = constrained_size(window, event.width, event.height);
+                events.push((Some(event.id), Event::Resize(width, height)));
+            }
         }
 
-        for event in self.keyboard_events.iter() {
-            match event.key_code {
-                Some(KeyCode::LControl) | Some(KeyCode::RControl) => {
-                    self.state.modifiers.ctrl = event.state == ElementState::Pressed;
-                    events.push(Event::Modifiers(self.state.modifiers));
-                }
-                Some(KeyCode::LAlt) | Some(KeyCode::RAlt) => {
-                    self.state.modifiers.alt = event.state == ElementState::Pressed;
-                    events.push(Event::Modifiers(self.state.modifiers));
-                }
-                Some(KeyCode::LShift) | Some(KeyCode::RShift) => {
-                    self.state.modifiers.shift = event.state == ElementState::Pressed;
-                    events.push(Event::Modifiers(self.state.modifiers));
-                }
-                Some(KeyCode::LWin) | Some(KeyCode::RWin) => {
-                    self.state.modifiers.shift = event.state == ElementState::Pressed;
-                    events.push(Event::Modifiers(self.state.modifiers));
+        for event in self.input.keyboard_events.iter() {
+            if let Some(key_code) = event.key_code {
+                let pressed = event.state == ElementState::Pressed;
+                if track_modifier(&mut self.state.modifiers, key_code, pressed) {
+                    events.push((None, Event::Modifiers(self.state.modifiers)));
                 }
-                _ => (),
             }
 
             match event {
@@ -103,8 +321,18 @@ impl<'a, M: Model + Send + Sync> UpdateUiSystemParams<'a, M> {
                     state: ElementState::Pressed,
                     ..
                 } => {
+                    if let Some(key_code) = *key_code {
+                        shortcuts.push(Shortcut::pressed(key_code, self.state.modifiers));
+                    }
+                    // pasted text is sent like typed text, in place of the key press
+                    #[cfg(feature = "clipboard")]
+                    if key_code.map_or(false, |key_code| is_paste(key_code, self.state.modifiers)) {
+                        let text = clipboard_text();
+                        events.extend(paste_events(&text, &self.input_settings).map(|event| (None, event)));
+                        continue;
+                    }
                     if let Some(key) = key_code.and_then(translate_key_code) {
-                        events.push(Event::Press(key));
+                        events.push((None, Event::Press(key)));
                     }
                 }
                 KeyboardInput {
@@ -113,35 +341,46 @@ impl<'a, M: Model + Send + Sync> UpdateUiSystemParams<'a, M> {
                     ..
                 } => {
                     if let Some(key) = key_code.and_then(translate_key_code) {
-                        events.push(Event::Release(key));
+                        events.push((None, Event::Release(key)));
                     }
                 }
             }
         }
 
-        for event in self.character_events.iter() {
-            events.push(Event::Text(event.char));
+        for event in self.input.character_events.iter() {
+            events.extend(text_event(event.char, &self.input_settings).map(|event| (None, event)));
         }
 
-        for event in self.cursor_moved_events.iter() {
-            events.push(Event::Cursor(
-                event.position.x,
-                window.height() as f32 - event.position.y,
-            ));
+        let cursor_origin = self.input_settings.cursor_origin;
+        let mut cursor_moves: Vec<(WindowId, Vec<Event>)> = Vec::new();
+        for event in self.input.cursor_moved_events.iter() {
+            let cursor = cursor_event(window_of(event.id), event.position, &self.input_settings);
+            match cursor_moves.iter_mut().find(|(id, _)| *id == event.id) {
+                Some((_, moves)) => moves.push(cursor),
+                None => cursor_moves.push((event.id, vec![cursor])),
+            }
+            self.state.pointer_window = event.id;
         }
+        for (id, moves) in cursor_moves {
+            let moves = coalesce_cursor_moves(moves.into_iter(), self.input_settings.coalesce_cursor_moves);
+            events.extend(moves.into_iter().map(|event| (Some(id), event)));
+        }
+        let pointer = Some(self.state.pointer_window);
+        let pointer_window = window_of(self.state.pointer_window);
 
-        for event in self.mouse_wheel_events.iter() {
-            events.push(Event::Scroll(event.x, event.y))
+        for event in self.input.mouse_wheel_events.iter() {
+            let shift = self.state.modifiers.shift;
+            events.push((pointer, scroll_event(event.x, event.y, shift, &self.input_settings)));
         }
 
-        for event in self.mouse_button_events.iter() {
+        for event in self.input.mouse_button_events.iter() {
             match event {
                 MouseButtonInput {
                     button,
                     state: ElementState::Pressed,
                 } => {
                     if let Some(key) = translate_mouse_button(*button) {
-                        events.push(Event::Press(key));
+                        events.push((pointer, Event::Press(key)));
                     }
                 }
                 MouseButtonInput {
@@ -149,67 +388,223 @@ impl<'a, M: Model + Send + Sync> UpdateUiSystemParams<'a, M> {
                     state: ElementState::Released,
                 } => {
                     if let Some(key) = translate_mouse_button(*button) {
-                        events.push(Event::Release(key));
+                        events.push((pointer, Event::Release(key)));
                     }
                 }
             }
         }
 
-        for (mut wrapper, mut draw, stylesheet) in self.query.iter_mut() {
-            if Some((window.width() as f32, window.height() as f32)) != wrapper.window {
-                wrapper.window = Some((window.width() as f32, window.height() as f32));
-                wrapper
-                    .ui
-                    .resize(Rectangle::from_wh(window.width() as f32, window.height() as f32));
+        for event in self.input.touch_events.iter() {
+            let touch = self.state.touch;
+            let cursor = cursor_event(pointer_window, event.position, &self.input_settings);
+            match event.phase {
+                TouchPhase::Started if touch.is_none() => {
+                    self.state.touch = Some(event.id);
+                    self.state.pressure = touch_pressure(event.force);
+                    events.push((pointer, cursor));
+                    events.push((pointer, Event::Press(Key::LeftMouseButton)));
+                }
+                TouchPhase::Moved if touch == Some(event.id) => {
+                    self.state.pressure = touch_pressure(event.force);
+                    events.push((pointer, cursor));
+                }
+                TouchPhase::Ended | TouchPhase::Cancelled if touch == Some(event.id) => {
+                    self.state.touch = None;
+                    self.state.pressure = None;
+                    events.push((pointer, cursor));
+                    events.push((pointer, Event::Release(Key::LeftMouseButton)));
+                }
+                _ => (),
             }
+        }
+
+        let modal = self.modals.top(self.entities);
+        let texture_targets = &self.texture_targets;
+
+        for (entity, mut wrapper, mut draw, stylesheet, style_override, target, ui_window) in self.query.iter_mut() {
+            // only the topmost modal receives input while one is open
+            let receives_input = modal.is_none() || modal == Some(entity);
+            // uis in a texture only receive the input that isn't tied to a window
+            let ui_window = match target {
+                Some(_) => None,
+                None => Some(ui_window.copied().unwrap_or_default().0),
+            };
+            let window = ui_window.and_then(window_of);
+            let texture_target = target.and_then(|UiRenderTarget(texture)| texture_targets.get(texture));
+            // a minimized window reports a size of 0x0, there is nothing to lay out or draw in that case
+            let minimized = window.map_or(false, |window| {
+                window.physical_width() == 0 || window.physical_height() == 0
+            });
+            let paused = minimized || (self.render_settings.pause_while_unfocused && unfocused);
+
+            let window_scale_factor = window.map(|window| window.scale_factor() as f32);
+            draw.scale_factor = wrapper.scale_factor.or(window_scale_factor).unwrap_or(1.0);
+
+            let (width, height) = match (texture_target, window) {
+                // the texture has as many pixels as the ui has physical pixels
+                (Some(target), _) => (
+                    target.width as f32 / draw.scale_factor,
+                    target.height as f32 / draw.scale_factor,
+                ),
+                (None, Some(window)) => constrained_size(window, window.width(), window.height()),
+                // without a window, the ui keeps the size it was created with or last laid out at
+                (None, None) => wrapper.size,
+            };
+            if !minimized && Some((width, height)) != wrapper.window {
+                let first_frame = wrapper.window.is_none();
+                wrapper.window = Some((width, height));
+                wrapper.size = (width, height);
+                wrapper.ui.resize(Rectangle::from_wh(width, height));
+
+                // the window may never be resized, so let the model know about its initial size
+                if first_frame {
+                    wrapper.ui.event(Event::Resize(width, height), &mut state);
 
-            if let Some(stylesheet) = stylesheet {
-                if let Some(stylesheet) = self.stylesheets.get(stylesheet) {
-                    wrapper.ui.replace_stylesheet(stylesheet.style.clone());
+                    // the cursor may already be inside the window, let hover states know without waiting for a move
+                    if let Some(position) = window.and_then(Window::cursor_position) {
+                        let event = cursor_event(window, position, &self.input_settings);
+                        wrapper.input.track(event, &self.input_settings);
+                        wrapper.ui.event(event, &mut state);
+                    }
+                }
+            }
+
+            let style = if let Some(style_override) = style_override {
+                Some((StyleSource::Override, style_override.style.clone()))
+            } else if let Some(handle) = stylesheet {
+                // fonts are loaded along with the stylesheet asset
+                self.stylesheets
+                    .get(handle)
+                    .map(|stylesheet| (StyleSource::Stylesheet(handle.clone_weak()), stylesheet.style.clone()))
+            } else {
+                // without any style the ui can't be rendered, so it isn't drawn either
+                None
+            };
+
+            if let Some((source, style)) = style {
+                wrapper.style_loaded = true;
+                if !wrapper.style.as_ref().map_or(false, |current| Arc::ptr_eq(current, &style)) {
+                    wrapper.ui.replace_stylesheet(style.clone());
+                    wrapper.style = Some(style);
+                    wrapper.style_source = source;
+
+                    // the pending draw refers to textures of the old stylesheet, don't render it
+                    draw.commands.clear();
+                    draw.updates.clear();
+                    wrapper.rebuild();
+                }
+            }
+
+            // send messages for triggered shortcuts
+            if let Some(shortcut_map) = self.shortcuts.as_ref().filter(|_| receives_input) {
+                for &shortcut in shortcuts.iter() {
+                    for message in shortcut_map.messages(shortcut) {
+                        wrapper.queue_command(Command::Message(message));
+                    }
                 }
             }
 
             // process async events
             wrapper.update_commands(&mut state);
 
+            // send releases for inputs that were force released
+            if std::mem::take(&mut wrapper.input.release_modifiers) {
+                // only for this ui, the modifiers are shared by all uis of the system
+                let released = State::default().modifiers;
+                wrapper.ui.event(Event::Modifiers(released), &mut state);
+            }
+            for key in std::mem::take(&mut wrapper.input.released) {
+                wrapper.input.track(Event::Release(key), &self.input_settings);
+                wrapper.ui.event(Event::Release(key), &mut state);
+            }
+
             // process input events
-            for &event in events.iter() {
+            for &(_, event) in events.iter().filter(|&&(origin, event)| {
+                (origin.is_none() || origin == ui_window) && (receives_input || matches!(event, Event::Resize(..)))
+            }) {
+                let event = match event {
+                    Event::Cursor(x, y) if window.is_none() && cursor_origin == CoordinateOrigin::TopLeft => {
+                        Event::Cursor(x, height - y)
+                    }
+                    event => event,
+                };
+                wrapper.input.track(event, &self.input_settings);
                 wrapper.ui.event(event, &mut state);
             }
+            // the pressure goes along with the touches, to the window the cursor moved in last
+            if receives_input && (ui_window.is_none() || ui_window == pointer) {
+                wrapper.input.pressure = self.state.pressure;
+            }
 
             // update ui drawing
-            if wrapper.ui.needs_redraw() {
-                let DrawList {
-                    updates,
-                    commands,
-                    vertices,
-                } = wrapper.ui.draw();
-
-                draw.updates.extend(updates.into_iter());
-                draw.commands = commands;
-                if !vertices.is_empty() {
-                    let old_buffer = draw
-                        .vertices
-                        .replace(self.render_resource_context.create_buffer_with_data(
-                            BufferInfo {
-                                size: vertices.len() * std::mem::size_of::<Vertex>(),
-                                buffer_usage: BufferUsage::VERTEX,
-                                mapped_at_creation: false,
-                            },
-                            vertices.as_bytes(),
-                        ));
-
-                    if let Some(b) = old_buffer {
-                        self.render_resource_context.remove_buffer(b)
-                    }
-                } else if let Some(b) = draw.vertices.take() {
-                    self.render_resource_context.remove_buffer(b)
+            let render_resource_context = match render_resource_context.as_ref() {
+                Some(context) if !paused && wrapper.style_loaded && wrapper.ui.needs_redraw() => context,
+                _ => continue,
+            };
+            let DrawList {
+                updates,
+                commands,
+                vertices,
+            } = wrapper.ui.draw();
+
+            draw.updates.extend(updates.into_iter());
+            draw.commands = commands;
+            draw.redrawn = true;
+            if draw.released_buffers.is_none() {
+                draw.released_buffers = Some(self.released_buffers.clone());
+            }
+            if !vertices.is_empty() {
+                let old_buffer = draw
+                    .vertices
+                    .replace(render_resource_context.create_buffer_with_data(
+                        BufferInfo {
+                            size: vertices.len() * std::mem::size_of::<Vertex>(),
+                            buffer_usage: BufferUsage::VERTEX,
+                            mapped_at_creation: false,
+                        },
+                        vertices.as_bytes(),
+                    ));
+
+                if let Some(b) = old_buffer {
+                    render_resource_context.remove_buffer(b)
                 }
+            } else if let Some(b) = draw.vertices.take() {
+                render_resource_context.remove_buffer(b)
             }
         }
     }
 }
 
+/// The text on the system clipboard, or nothing if there is no text or the clipboard can't be read.
+#[cfg(feature = "clipboard")]
+fn clipboard_text() -> String {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .unwrap_or_else(|error| {
+            log::warn!("failed to read the clipboard: {}", error);
+            String::new()
+        })
+}
+
+/// Whether pressing `key_code` with `modifiers` pastes from the clipboard: cmd+v on macOS, ctrl+v elsewhere.
+#[cfg(feature = "clipboard")]
+fn is_paste(key_code: KeyCode, modifiers: Modifiers) -> bool {
+    #[cfg(target_os = "macos")]
+    let modifier = modifiers.logo;
+    #[cfg(not(target_os = "macos"))]
+    let modifier = modifiers.ctrl;
+    key_code == KeyCode::V && modifier
+}
+
+/// The text events of `text` pasted from the clipboard. Control characters are dropped, so pasted text is
+/// flattened: line breaks and tabs disappear and multiple lines end up as one, without anything in between.
+#[cfg(feature = "clipboard")]
+fn paste_events<'s>(text: &'s str, settings: &'s UiInputSettings) -> impl Iterator<Item = Event> + 's {
+    text.chars()
+        .filter(|character| !character.is_control())
+        .filter_map(move |character| text_event(character, settings))
+}
+
 fn translate_key_code(key_code: KeyCode) -> Option<Key> {
     Some(match key_code {
         KeyCode::Key1 => Key::Key1,
@@ -222,6 +617,16 @@ fn translate_key_code(key_code: KeyCode) -> Option<Key> {
         KeyCode::Key8 => Key::Key8,
         KeyCode::Key9 => Key::Key9,
         KeyCode::Key0 => Key::Key0,
+        KeyCode::Numpad1 => Key::Key1,
+        KeyCode::Numpad2 => Key::Key2,
+        KeyCode::Numpad3 => Key::Key3,
+        KeyCode::Numpad4 => Key::Key4,
+        KeyCode::Numpad5 => Key::Key5,
+        KeyCode::Numpad6 => Key::Key6,
+        KeyCode::Numpad7 => Key::Key7,
+        KeyCode::Numpad8 => Key::Key8,
+        KeyCode::Numpad9 => Key::Key9,
+        KeyCode::Numpad0 => Key::Key0,
         KeyCode::A => Key::A,
         KeyCode::B => Key::B,
         KeyCode::C => Key::C,
@@ -250,22 +655,116 @@ fn translate_key_code(key_code: KeyCode) -> Option<Key> {
         KeyCode::Z => Key::Z,
         KeyCode::Escape => Key::Escape,
         KeyCode::Tab => Key::Tab,
-        KeyCode::LShift => Key::Shift,
-        KeyCode::LControl => Key::Ctrl,
-        KeyCode::LAlt => Key::Alt,
+        KeyCode::LShift | KeyCode::RShift => Key::Shift,
+        KeyCode::LControl | KeyCode::RControl => Key::Ctrl,
+        KeyCode::LAlt | KeyCode::RAlt => Key::Alt,
         KeyCode::Space => Key::Space,
-        KeyCode::Return => Key::Enter,
+        KeyCode::Return | KeyCode::NumpadEnter => Key::Enter,
         KeyCode::Back => Key::Backspace,
         KeyCode::Home => Key::Home,
         KeyCode::End => Key::End,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Insert => Key::Insert,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
         KeyCode::Left => Key::Left,
         KeyCode::Right => Key::Right,
         KeyCode::Up => Key::Up,
         KeyCode::Down => Key::Down,
+        KeyCode::F1 => Key::F1,
+        KeyCode::F2 => Key::F2,
+        KeyCode::F3 => Key::F3,
+        KeyCode::F4 => Key::F4,
+        KeyCode::F5 => Key::F5,
+        KeyCode::F6 => Key::F6,
+        KeyCode::F7 => Key::F7,
+        KeyCode::F8 => Key::F8,
+        KeyCode::F9 => Key::F9,
+        KeyCode::F10 => Key::F10,
+        KeyCode::F11 => Key::F11,
+        KeyCode::F12 => Key::F12,
         _ => None?,
     })
 }
 
+/// Updates `modifiers` if `key_code` is one of the modifier keys. Returns whether it was.
+fn track_modifier(modifiers: &mut Modifiers, key_code: KeyCode, pressed: bool) -> bool {
+    match key_code {
+        KeyCode::LControl | KeyCode::RControl => modifiers.ctrl = pressed,
+        KeyCode::LAlt | KeyCode::RAlt => modifiers.alt = pressed,
+        KeyCode::LShift | KeyCode::RShift => modifiers.shift = pressed,
+        KeyCode::LWin | KeyCode::RWin => modifiers.logo = pressed,
+        _ => return false,
+    }
+    true
+}
+
+/// The cursor moves of a frame, or only the last one of them if `coalesce` is set.
+fn coalesce_cursor_moves(cursor_moves: impl Iterator<Item = Event>, coalesce: bool) -> Vec<Event> {
+    if coalesce {
+        cursor_moves.last().into_iter().collect()
+    } else {
+        cursor_moves.collect()
+    }
+}
+
+/// The text event of a typed or pasted `character`. Characters that aren't supported are replaced by the fallback
+/// character, or dropped without one.
+fn text_event(character: char, settings: &UiInputSettings) -> Option<Event> {
+    match settings.supported_characters {
+        Some(supported) if !supported(character) => settings.fallback_character.map(Event::Text),
+        _ => Some(Event::Text(character)),
+    }
+}
+
+/// The cursor event of a `position` in `window`, which bevy reports from the bottom left corner of the window.
+fn cursor_event(window: Option<&Window>, position: Vec2, settings: &UiInputSettings) -> Event {
+    let cursor_scale = match window {
+        Some(window) if settings.physical_cursor => window.scale_factor() as f32,
+        _ => 1.0,
+    };
+    // bevy reports positions in logical pixels, so they're flipped against the logical height before scaling
+    let y = match (settings.cursor_origin, window) {
+        (CoordinateOrigin::TopLeft, Some(window)) => window.height() as f32 - position.y,
+        // without a window, every ui flips the position against its own height when it receives it
+        (CoordinateOrigin::TopLeft, None) | (CoordinateOrigin::BottomLeft, _) => position.y,
+    };
+    Event::Cursor(position.x * cursor_scale, y * cursor_scale)
+}
+
+/// The scroll of a mouse wheel movement. The vertical wheel scrolls horizontally while shift is held,
+/// if that's enabled in the `settings`.
+fn scroll_event(x: f32, y: f32, shift: bool, settings: &UiInputSettings) -> Event {
+    if settings.shift_scrolls_horizontally && shift && x == 0.0 {
+        Event::Scroll(y, 0.0)
+    } else {
+        Event::Scroll(x, y)
+    }
+}
+
+/// The force of a touch, normalized to 0.0 to 1.0. `None` if the device doesn't report it.
+fn touch_pressure(force: Option<ForceTouch>) -> Option<f32> {
+    match force? {
+        ForceTouch::Calibrated {
+            force,
+            max_possible_force,
+            ..
+        } if max_possible_force > 0.0 => Some((force / max_possible_force) as f32),
+        ForceTouch::Calibrated { .. } => None,
+        ForceTouch::Normalized(force) => Some(force as f32),
+    }
+}
+
+/// Clamps a window size to the resize constraints of the window,
+/// so layout never runs against a size the window can't actually have.
+fn constrained_size(window: &Window, width: f32, height: f32) -> (f32, f32) {
+    let constraints = window.resize_constraints();
+    (
+        width.max(constraints.min_width).min(constraints.max_width),
+        height.max(constraints.min_height).min(constraints.max_height),
+    )
+}
+
 fn translate_mouse_button(button: MouseButton) -> Option<Key> {
     Some(match button {
         MouseButton::Left => Key::LeftMouseButton,
@@ -274,3 +773,600 @@ fn translate_mouse_button(button: MouseButton) -> Option<Key> {
         _ => None?,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::window::WindowResizeConstraints;
+
+    use super::*;
+    use crate::target::UiTextureTarget;
+    use crate::test_util::{
+        add_secondary_window, add_window_with, click_up, click_up_in_window, headless_app, load_stylesheet,
+        resize_window, send, spawn_counter, spawn_counter_with_stylesheet, style, stylesheet_handle, target_texture,
+        window_app, Counter, Message, UP,
+    };
+
+    fn track(events: &[Event]) -> InputTracker {
+        let mut input = InputTracker::default();
+        for &event in events {
+            input.track(event, &UiInputSettings::default());
+        }
+        input
+    }
+
+    fn cursor_position(event: Event) -> (f32, f32) {
+        match event {
+            Event::Cursor(x, y) => (x, y),
+            event => panic!("{:?} isn't a cursor event", event),
+        }
+    }
+
+    /// The characters that the uis receive when `text` is typed.
+    fn typed(text: &str, settings: &UiInputSettings) -> String {
+        text.chars()
+            .filter_map(|character| text_event(character, settings))
+            .map(|event| match event {
+                Event::Text(character) => character,
+                event => panic!("{:?} isn't a text event", event),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn release_after_drag_drops() {
+        let input = track(&[
+            Event::Cursor(10.0, 10.0),
+            Event::Press(Key::LeftMouseButton),
+            Event::Cursor(20.0, 10.0),
+            Event::Release(Key::LeftMouseButton),
+        ]);
+        assert_eq!(
+            input.gesture,
+            PointerGesture::Dropped {
+                origin: (10.0, 10.0),
+                position: (20.0, 10.0),
+            }
+        );
+    }
+
+    #[test]
+    fn release_within_drag_threshold_clicks() {
+        let input = track(&[
+            Event::Cursor(10.0, 10.0),
+            Event::Press(Key::LeftMouseButton),
+            Event::Cursor(12.0, 11.0),
+            Event::Release(Key::LeftMouseButton),
+        ]);
+        assert_eq!(input.gesture, PointerGesture::Clicked { position: (12.0, 11.0) });
+    }
+
+    #[test]
+    fn cursor_moves_are_coalesced() {
+        let moves = (0..5).map(|i| Event::Cursor(i as f32, 0.0));
+        let coalesced = coalesce_cursor_moves(moves.clone(), true);
+        assert_eq!(coalesced.len(), 1);
+        assert!(matches!(coalesced[0], Event::Cursor(x, _) if x == 4.0));
+        assert_eq!(coalesce_cursor_moves(moves, false).len(), 5);
+    }
+
+    #[test]
+    fn unsupported_characters_are_dropped_or_replaced() {
+        fn ascii(character: char) -> bool {
+            character.is_ascii()
+        }
+
+        assert_eq!(typed("añb", &UiInputSettings::default()), "añb");
+
+        let mut settings = UiInputSettings {
+            supported_characters: Some(ascii as fn(char) -> bool),
+            ..Default::default()
+        };
+        assert_eq!(typed("añb", &settings), "ab");
+
+        settings.fallback_character = Some('?');
+        assert_eq!(typed("añb", &settings), "a?b");
+    }
+
+    #[test]
+    fn composed_characters_survive_the_character_filter() {
+        fn not_control(character: char) -> bool {
+            !character.is_control()
+        }
+
+        let settings = UiInputSettings {
+            supported_characters: Some(not_control as fn(char) -> bool),
+            ..Default::default()
+        };
+        // precomposed, and with a combining accent that arrives as a character of its own
+        assert_eq!(typed("café", &settings), "café");
+        assert_eq!(typed("cafe\u{301}", &settings), "cafe\u{301}");
+        assert_eq!(typed("café\u{7}", &settings), "café");
+    }
+
+    #[test]
+    #[cfg(feature = "clipboard")]
+    fn paste_is_cmd_v_on_macos_and_ctrl_v_elsewhere() {
+        let mut ctrl = State::default().modifiers;
+        ctrl.ctrl = true;
+        let mut logo = State::default().modifiers;
+        logo.logo = true;
+
+        assert_eq!(is_paste(KeyCode::V, ctrl), cfg!(not(target_os = "macos")));
+        assert_eq!(is_paste(KeyCode::V, logo), cfg!(target_os = "macos"));
+        assert!(!is_paste(KeyCode::V, State::default().modifiers));
+        assert!(!is_paste(KeyCode::C, ctrl));
+        assert!(!is_paste(KeyCode::C, logo));
+    }
+
+    #[test]
+    #[cfg(feature = "clipboard")]
+    fn pasted_lines_are_flattened() {
+        let text: String = paste_events("one\r\ntwo\tthree", &UiInputSettings::default())
+            .map(|event| match event {
+                Event::Text(character) => character,
+                event => panic!("{:?} isn't a text event", event),
+            })
+            .collect();
+        assert_eq!(text, "onetwothree");
+    }
+
+    #[test]
+    fn bottom_left_cursor_is_not_flipped() {
+        let window = Window::new(WindowId::primary(), &WindowDescriptor::default(), 800, 600, 1.0, None);
+        let position = Vec2::new(10.0, 20.0);
+        let cursor = |settings: &UiInputSettings| cursor_position(cursor_event(Some(&window), position, settings));
+        let settings = UiInputSettings {
+            cursor_origin: CoordinateOrigin::BottomLeft,
+            ..Default::default()
+        };
+        assert_eq!(cursor(&settings), (10.0, 20.0));
+
+        // by default, it's flipped against the height of the window
+        assert_eq!(cursor(&UiInputSettings::default()), (10.0, 580.0));
+    }
+
+    #[test]
+    fn calibrated_touch_force_is_normalized() {
+        let force = |force, max_possible_force| ForceTouch::Calibrated {
+            force,
+            max_possible_force,
+            altitude_angle: None,
+        };
+        assert_eq!(touch_pressure(Some(force(1.5, 6.0))), Some(0.25));
+        assert_eq!(touch_pressure(Some(force(1.5, 0.0))), None);
+        assert_eq!(touch_pressure(Some(ForceTouch::Normalized(0.75))), Some(0.75));
+        assert_eq!(touch_pressure(None), None);
+    }
+
+    #[test]
+    fn touch_pressure_is_forwarded_while_touching() {
+        let mut app = window_app();
+        let entity = spawn_counter(&mut app.world);
+        app.update();
+
+        let touch = |app: &mut App, phase, force: Option<f64>| {
+            send(
+                app,
+                TouchInput {
+                    phase,
+                    position: Vec2::new(UP.0, 600.0 - UP.1),
+                    force: force.map(ForceTouch::Normalized),
+                    id: 0,
+                },
+            );
+            app.update();
+            app.world.get::<Ui<Counter>>(entity).unwrap().pressure()
+        };
+        assert_eq!(touch(&mut app, TouchPhase::Started, Some(0.5)), Some(0.5));
+        // devices without force support fall back to no pressure at all
+        assert_eq!(touch(&mut app, TouchPhase::Moved, None), None);
+        assert_eq!(touch(&mut app, TouchPhase::Moved, Some(0.8)), Some(0.8));
+        assert_eq!(touch(&mut app, TouchPhase::Ended, Some(0.8)), None);
+    }
+
+    #[test]
+    fn physical_cursor_is_flipped_and_then_scaled() {
+        // 300 logical pixels high
+        let window = Window::new(WindowId::primary(), &WindowDescriptor::default(), 800, 600, 2.0, None);
+        let settings = UiInputSettings {
+            physical_cursor: true,
+            ..Default::default()
+        };
+        let cursor = cursor_event(Some(&window), Vec2::new(10.0, 290.0), &settings);
+        assert_eq!(cursor_position(cursor), (20.0, 20.0));
+
+        // logical pixels are sent as they are
+        let cursor = cursor_event(Some(&window), Vec2::new(10.0, 290.0), &UiInputSettings::default());
+        assert_eq!(cursor_position(cursor), (10.0, 10.0));
+    }
+
+    #[test]
+    fn shift_scrolls_horizontally_only_when_enabled() {
+        let mut settings = UiInputSettings::default();
+        assert!(matches!(scroll_event(0.0, 3.0, true, &settings), Event::Scroll(x, y) if x == 0.0 && y == 3.0));
+
+        settings.shift_scrolls_horizontally = true;
+        assert!(matches!(scroll_event(0.0, 3.0, true, &settings), Event::Scroll(x, y) if x == 3.0 && y == 0.0));
+        // without shift, or with a horizontal wheel, the wheel scrolls as it is
+        assert!(matches!(scroll_event(0.0, 3.0, false, &settings), Event::Scroll(x, y) if x == 0.0 && y == 3.0));
+        assert!(matches!(scroll_event(2.0, 3.0, true, &settings), Event::Scroll(x, y) if x == 2.0 && y == 3.0));
+    }
+
+    #[test]
+    fn headless_click_reaches_model() {
+        let mut app = headless_app();
+        let entity = spawn_counter(&mut app.world);
+
+        // without a window, cursor positions are flipped against the size the ui was created with
+        click_up(&mut app, 720.0);
+        app.update();
+
+        let ui = app.world.get::<Ui<Counter>>(entity).unwrap();
+        assert_eq!(ui.model().value, 1);
+        assert_eq!(ui.size, (1280.0, 720.0));
+    }
+
+    #[test]
+    fn resize_below_the_minimum_size_lays_out_at_the_minimum() {
+        let mut app = headless_app();
+        let descriptor = WindowDescriptor {
+            resize_constraints: WindowResizeConstraints {
+                min_width: 400.0,
+                min_height: 300.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        add_window_with(&mut app, &descriptor, 800, 600);
+        let entity = spawn_counter(&mut app.world);
+        app.update();
+
+        resize_window(&mut app, 200, 100);
+        app.update();
+        assert_eq!(app.world.get::<Ui<Counter>>(entity).unwrap().size, (400.0, 300.0));
+    }
+
+    #[test]
+    fn cursor_moves_reach_the_ui_before_clicks_of_the_same_frame() {
+        let mut app = window_app();
+        let entity = spawn_counter(&mut app.world);
+        let move_cursor = |app: &mut App, y: f32| {
+            send(
+                app,
+                CursorMoved {
+                    id: WindowId::primary(),
+                    position: Vec2::new(UP.0, 600.0 - y),
+                },
+            );
+        };
+        // start out on the "down" button, right below "up"
+        move_cursor(&mut app, UP.1 + 40.0);
+        app.update();
+
+        // the click is sent before the move, but still hits the button the cursor moved to
+        for &state in [ElementState::Pressed, ElementState::Released].iter() {
+            send(
+                &mut app,
+                MouseButtonInput {
+                    button: MouseButton::Left,
+                    state,
+                },
+            );
+        }
+        move_cursor(&mut app, UP.1);
+        app.update();
+
+        assert_eq!(app.world.get::<Ui<Counter>>(entity).unwrap().model().value, 1);
+    }
+
+    #[test]
+    fn texture_target_ui_has_texture_size_and_no_pointer_input() {
+        let mut app = window_app();
+        let target = UiTextureTarget::new(target_texture(), 400, 300);
+        app.world.insert_resource(UiTextureTargets(vec![target]));
+        let entity = spawn_counter(&mut app.world);
+        app.world.entity_mut(entity).insert(UiRenderTarget(target_texture()));
+
+        click_up(&mut app, 600.0);
+        app.update();
+
+        let ui = app.world.get::<Ui<Counter>>(entity).unwrap();
+        assert_eq!(ui.model().value, 0);
+        assert_eq!(ui.size, (400.0, 300.0));
+    }
+
+    #[test]
+    fn minimized_window_ui_is_neither_laid_out_nor_drawn() {
+        use crate::test_util::{add_window, insert_recording_context};
+
+        let mut app = headless_app();
+        insert_recording_context(&mut app);
+        add_window(&mut app, 800, 0);
+        let entity = spawn_counter(&mut app.world);
+        app.update();
+
+        let ui = app.world.get::<Ui<Counter>>(entity).unwrap();
+        assert_eq!(ui.size, (1280.0, 720.0));
+        assert_eq!(ui.window, None);
+        assert!(app.world.get::<UiDraw>(entity).unwrap().vertices.is_none());
+
+        // once the window is restored, the ui is laid out at its size and drawn
+        resize_window(&mut app, 800, 600);
+        app.update();
+
+        let ui = app.world.get::<Ui<Counter>>(entity).unwrap();
+        assert_eq!(ui.size, (800.0, 600.0));
+        assert!(app.world.get::<UiDraw>(entity).unwrap().vertices.is_some());
+    }
+
+    #[test]
+    fn rebuild_and_model_mut_draw_changes_to_the_model() {
+        use std::sync::atomic::Ordering;
+
+        use crate::test_util::{insert_recording_context, spawn_toggles, take_calls, Toggles};
+        use crate::testing::ResourceCall;
+
+        let mut app = headless_app();
+        insert_recording_context(&mut app);
+        let toggles = Toggles::default();
+        let shared = toggles.shared.clone();
+        let entity = spawn_toggles(&mut app.world, toggles);
+        // the size of the vertex buffer that was created for a redraw, if any
+        let redrawn_vertices = |app: &App| {
+            take_calls(app).into_iter().find_map(|call| match call {
+                ResourceCall::CreateBuffer { info, .. } if info.buffer_usage == BufferUsage::VERTEX => Some(info.size),
+                _ => None,
+            })
+        };
+        app.update();
+        let base = redrawn_vertices(&app).unwrap();
+
+        shared.store(true, Ordering::Relaxed);
+        app.update();
+        assert_eq!(redrawn_vertices(&app), None);
+
+        app.world.get_mut::<Ui<Toggles>>(entity).unwrap().rebuild();
+        app.update();
+        let shared_drawn = redrawn_vertices(&app).unwrap();
+        assert!(shared_drawn > base);
+
+        // mutable access to the model rebuilds the view by itself
+        app.world.get_mut::<Ui<Toggles>>(entity).unwrap().model_mut().local = true;
+        app.update();
+        assert!(redrawn_vertices(&app).unwrap() > shared_drawn);
+    }
+
+    #[test]
+    fn unfocused_uis_are_redrawn_once_a_window_has_focus_again() {
+        use crate::test_util::insert_recording_context;
+
+        let mut app = window_app();
+        insert_recording_context(&mut app);
+        app.world
+            .get_resource_mut::<UiRenderSettings>()
+            .unwrap()
+            .pause_while_unfocused = true;
+        let entity = spawn_counter(&mut app.world);
+        app.update();
+        let vertices = |app: &App| app.world.get::<UiDraw>(entity).unwrap().vertices;
+        let drawn = vertices(&app);
+        assert!(drawn.is_some());
+
+        let set_focused = |app: &mut App, focused| {
+            let mut windows = app.world.get_resource_mut::<Windows>().unwrap();
+            let window = windows.get_primary_mut().unwrap();
+            window.update_focused_status_from_backend(focused);
+        };
+        set_focused(&mut app, false);
+        resize_window(&mut app, 640, 480);
+        app.update();
+        assert_eq!(vertices(&app), drawn);
+
+        set_focused(&mut app, true);
+        app.update();
+        assert_ne!(vertices(&app), drawn);
+    }
+
+    #[test]
+    fn new_ui_receives_the_cursor_position_of_its_window() {
+        let mut app = window_app();
+        let set_cursor = |app: &mut App, position| {
+            let mut windows = app.world.get_resource_mut::<Windows>().unwrap();
+            let window = windows.get_primary_mut().unwrap();
+            window.update_cursor_position_from_backend(position);
+        };
+        set_cursor(&mut app, Some(Vec2::new(30.0, 500.0)));
+        let entity = spawn_counter(&mut app.world);
+        app.update();
+        // bevy's cursor positions start at the bottom of the 600 pixels high window
+        assert_eq!(
+            app.world.get::<Ui<Counter>>(entity).unwrap().input.cursor,
+            (30.0, 100.0)
+        );
+
+        // without a cursor in the window, the ui keeps its default position
+        set_cursor(&mut app, None);
+        let entity = spawn_counter(&mut app.world);
+        app.update();
+        assert_eq!(app.world.get::<Ui<Counter>>(entity).unwrap().input.cursor, (0.0, 0.0));
+    }
+
+    #[test]
+    fn secondary_window_ui_has_its_own_size_and_input() {
+        let mut app = window_app();
+        let window = add_secondary_window(&mut app, 400, 300);
+        let primary_ui = spawn_counter(&mut app.world);
+        let secondary_ui = spawn_counter(&mut app.world);
+        app.world.entity_mut(secondary_ui).insert(UiWindow(window));
+        app.update();
+
+        let ui = |entity| app.world.get::<Ui<Counter>>(entity).unwrap();
+        assert_eq!(ui(primary_ui).size, (800.0, 600.0));
+        assert_eq!(ui(secondary_ui).size, (400.0, 300.0));
+
+        // the mouse buttons follow the cursor into the secondary window
+        click_up_in_window(&mut app, window, 300.0);
+        app.update();
+
+        let ui = |entity| app.world.get::<Ui<Counter>>(entity).unwrap();
+        assert_eq!(ui(primary_ui).model().value, 0);
+        assert_eq!(ui(secondary_ui).model().value, 1);
+    }
+
+    #[test]
+    fn style_override_only_replaces_the_style_of_its_ui() {
+        let mut app = headless_app();
+        let stylesheet = stylesheet_handle();
+        let asset_style = style();
+        load_stylesheet(&mut app, &stylesheet, asset_style.clone());
+        let overridden = spawn_counter_with_stylesheet(&mut app.world, stylesheet.clone());
+        let shared = spawn_counter_with_stylesheet(&mut app.world, stylesheet);
+        let override_style = style();
+        app.world
+            .entity_mut(overridden)
+            .insert(StyleOverride::new(override_style.clone()));
+        app.update();
+
+        let style = |app: &App, entity| app.world.get::<Ui<Counter>>(entity).unwrap().style.clone().unwrap();
+        assert!(Arc::ptr_eq(&style(&app, overridden), &override_style));
+        assert!(Arc::ptr_eq(&style(&app, shared), &asset_style));
+
+        // removing the override reverts the ui back to the asset
+        app.world.entity_mut(overridden).remove::<StyleOverride>();
+        app.update();
+        assert!(Arc::ptr_eq(&style(&app, overridden), &asset_style));
+        assert!(Arc::ptr_eq(&style(&app, shared), &asset_style));
+    }
+
+    #[test]
+    fn style_source_follows_the_override() {
+        let mut app = headless_app();
+        let stylesheet = stylesheet_handle();
+        load_stylesheet(&mut app, &stylesheet, style());
+        let entity = spawn_counter_with_stylesheet(&mut app.world, stylesheet.clone());
+        app.update();
+        let style_source = |app: &App| app.world.get::<Ui<Counter>>(entity).unwrap().style_source().clone();
+        assert_eq!(style_source(&app), StyleSource::Stylesheet(stylesheet.clone()));
+
+        app.world.entity_mut(entity).insert(StyleOverride::new(style()));
+        app.update();
+        assert_eq!(style_source(&app), StyleSource::Override);
+
+        app.world.entity_mut(entity).remove::<StyleOverride>();
+        app.update();
+        assert_eq!(style_source(&app), StyleSource::Stylesheet(stylesheet));
+    }
+
+    #[test]
+    fn discarded_commands_never_reach_the_swapped_in_model() {
+        let mut app = headless_app();
+        let entity = spawn_counter(&mut app.world);
+        app.update();
+
+        let mut ui = app.world.get_mut::<Ui<Counter>>(entity).unwrap();
+        ui.queue_message(Message::Up).unwrap();
+        *ui.model_mut() = Counter {
+            value: 10,
+            ..Counter::default()
+        };
+        assert_eq!(ui.discard_pending_commands(), 1);
+        app.update();
+
+        assert_eq!(app.world.get::<Ui<Counter>>(entity).unwrap().model().value, 10);
+    }
+
+    #[test]
+    fn release_all_inputs_releases_keys_and_modifiers() {
+        let mut app = window_app();
+        app.world
+            .insert_resource(ShortcutMap::<Counter>::new().with(Shortcut::new(KeyCode::Z).ctrl(), Message::Down));
+        let entity = spawn_counter(&mut app.world);
+        let other = spawn_counter(&mut app.world);
+        let press = |key_code| KeyboardInput {
+            scan_code: 0,
+            key_code: Some(key_code),
+            state: ElementState::Pressed,
+        };
+
+        send(&mut app, press(KeyCode::LControl));
+        send(
+            &mut app,
+            MouseButtonInput {
+                button: MouseButton::Left,
+                state: ElementState::Pressed,
+            },
+        );
+        app.update();
+        let mut ui = app.world.get_mut::<Ui<Counter>>(entity).unwrap();
+        assert!(ui.is_pressed(Key::Ctrl));
+        assert!(ui.is_pressed(Key::LeftMouseButton));
+
+        ui.release_all_inputs();
+        app.update();
+        let ui = app.world.get::<Ui<Counter>>(entity).unwrap();
+        assert!(!ui.is_pressed(Key::Ctrl));
+        assert!(!ui.is_pressed(Key::LeftMouseButton));
+        // the releases have been sent to the ui
+        assert!(ui.input.released.is_empty());
+        assert!(matches!(ui.gesture(), PointerGesture::Clicked { .. }));
+
+        // ctrl is still held for the other uis, and for shortcuts
+        assert!(app.world.get::<Ui<Counter>>(other).unwrap().is_pressed(Key::Ctrl));
+        send(&mut app, press(KeyCode::Z));
+        app.update();
+        assert_eq!(app.world.get::<Ui<Counter>>(other).unwrap().model().value, -1);
+    }
+
+    #[test]
+    fn windows_key_is_logo() {
+        let mut modifiers = State::default().modifiers;
+        assert!(track_modifier(&mut modifiers, KeyCode::LWin, true));
+        assert!(modifiers.logo);
+        assert!(!modifiers.shift);
+        assert!(!modifiers.ctrl);
+        assert!(!modifiers.alt);
+
+        assert!(track_modifier(&mut modifiers, KeyCode::RWin, false));
+        assert!(!modifiers.logo);
+        assert!(!track_modifier(&mut modifiers, KeyCode::A, true));
+    }
+
+    #[test]
+    fn translated_key_codes() {
+        let keys = [
+            (KeyCode::Delete, Key::Delete),
+            (KeyCode::Insert, Key::Insert),
+            (KeyCode::PageUp, Key::PageUp),
+            (KeyCode::PageDown, Key::PageDown),
+            (KeyCode::F1, Key::F1),
+            (KeyCode::F2, Key::F2),
+            (KeyCode::F3, Key::F3),
+            (KeyCode::F4, Key::F4),
+            (KeyCode::F5, Key::F5),
+            (KeyCode::F6, Key::F6),
+            (KeyCode::F7, Key::F7),
+            (KeyCode::F8, Key::F8),
+            (KeyCode::F9, Key::F9),
+            (KeyCode::F10, Key::F10),
+            (KeyCode::F11, Key::F11),
+            (KeyCode::F12, Key::F12),
+            (KeyCode::RShift, Key::Shift),
+            (KeyCode::RControl, Key::Ctrl),
+            (KeyCode::RAlt, Key::Alt),
+            (KeyCode::Numpad0, Key::Key0),
+            (KeyCode::Numpad1, Key::Key1),
+            (KeyCode::Numpad2, Key::Key2),
+            (KeyCode::Numpad3, Key::Key3),
+            (KeyCode::Numpad4, Key::Key4),
+            (KeyCode::Numpad5, Key::Key5),
+            (KeyCode::Numpad6, Key::Key6),
+            (KeyCode::Numpad7, Key::Key7),
+            (KeyCode::Numpad8, Key::Key8),
+            (KeyCode::Numpad9, Key::Key9),
+            (KeyCode::NumpadEnter, Key::Enter),
+        ];
+        for &(key_code, key) in keys.iter() {
+            assert_eq!(translate_key_code(key_code), Some(key), "{:?}", key_code);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use bevy_pixel_widgets::event::{Event, Key};
+use bevy_pixel_widgets::prelude::*;
+use bevy_pixel_widgets::{widget, DisabledLoader, UpdateModel};
+use futures_lite::future::block_on;
+
+struct Counter {
+    pub value: i32,
+    pub state: ManagedState<String>,
+}
+
+#[derive(Clone)]
+enum Message {
+    UpPressed,
+    DownPressed,
+}
+
+impl Model for Counter {
+    type Message = Message;
+
+    // buttons without text, so the ui doesn't need a font
+    fn view(&mut self) -> widget::Node<Message> {
+        let mut state = self.state.tracker();
+        widget::Column::new()
+            .push(widget::Button::new(state.get("up"), widget::Column::new()).on_clicked(Message::UpPressed))
+            .push(widget::Button::new(state.get("down"), widget::Column::new()).on_clicked(Message::DownPressed))
+            .into_node()
+    }
+}
+
+impl<'a> UpdateModel<'a> for Counter {
+    type State = ();
+
+    fn update(&mut self, message: Self::Message, _: &mut Self::State) -> Vec<Command<Message>> {
+        match message {
+            Message::UpPressed => {
+                self.value += 1;
+                Vec::new()
+            }
+            Message::DownPressed => {
+                self.value -= 1;
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Drives a `Ui` without an app, window or gpu, as a template for testing models.
+#[test]
+fn click_and_queued_messages_reach_the_model() {
+    let mut ui = Ui::new(Counter {
+        value: 0,
+        state: Default::default(),
+    });
+
+    // input is sent straight to the pixel_widgets ui. buttons of a fixed size put "Up" at the top left,
+    // so its center is at (50, 20) in ui coordinates, where y points down from the top.
+    let style = block_on(Style::load_from_memory(
+        "button { width: 100; height: 40; }".as_bytes(),
+        &DisabledLoader,
+        512,
+        0,
+    ));
+    ui.replace_stylesheet(Arc::new(style.unwrap()));
+    ui.event(Event::Cursor(50.0, 20.0), &mut ());
+    ui.event(Event::Press(Key::LeftMouseButton), &mut ());
+    ui.event(Event::Release(Key::LeftMouseButton), &mut ());
+    assert_eq!(ui.model().value, 1);
+
+    // messages are queued like the ones of async commands, and processed by `update_commands`
+    ui.queue_message(Message::UpPressed).unwrap();
+    ui.queue_message(Message::UpPressed).unwrap();
+    ui.queue_message(Message::DownPressed).unwrap();
+    ui.update_commands(&mut ());
+    assert_eq!(ui.model().value, 2);
+}